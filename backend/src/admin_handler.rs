@@ -0,0 +1,138 @@
+use serde::Deserialize;
+use warp::{reject, reply::json, Reply};
+
+use crate::{battlefun, game_handler::GenericResponse, BattleFunInstance, Result};
+use battlefun::{
+    admin::{Role, Staff},
+    protocol::AnnouncementKind,
+    GameId, PlayerId,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct ResignRequest {
+    player_id: PlayerId,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RoleRequest {
+    role: Role,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnnouncementRequest {
+    #[serde(default)]
+    kind: AnnouncementKind,
+    message: String,
+}
+
+pub async fn list_players_handler(
+    _staff: Staff,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    Ok(json(&battlefun_instance.player_summaries()))
+}
+
+pub async fn list_games_handler(
+    _staff: Staff,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    Ok(json(&battlefun_instance.game_summaries()))
+}
+
+pub async fn abandon_game_handler(
+    game_id: GameId,
+    staff: Staff,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    battlefun_instance
+        .abandon_game(game_id)
+        .map_err(reject::custom)?;
+
+    eprintln!("{} abandoned game {}", staff, game_id);
+    Ok(json(&GenericResponse::success()))
+}
+
+pub async fn resign_game_handler(
+    game_id: GameId,
+    request: ResignRequest,
+    staff: Staff,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    battlefun_instance
+        .gamemaster
+        .resign(game_id, request.player_id)
+        .map_err(reject::custom)?;
+
+    eprintln!(
+        "{} resigned game {} for {}",
+        staff, game_id, request.player_id
+    );
+    Ok(json(&GenericResponse::success()))
+}
+
+pub async fn kick_handler(
+    player_id: PlayerId,
+    staff: Staff,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    battlefun_instance
+        .kick(staff, &player_id)
+        .map_err(reject::custom)?;
+
+    eprintln!("{} kicked {}", staff, player_id);
+    Ok(json(&GenericResponse::success()))
+}
+
+pub async fn ban_handler(
+    player_id: PlayerId,
+    staff: Staff,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    battlefun_instance
+        .set_banned(staff, &player_id, true)
+        .map_err(reject::custom)?;
+
+    eprintln!("{} banned {}", staff, player_id);
+    Ok(json(&GenericResponse::success()))
+}
+
+pub async fn unban_handler(
+    player_id: PlayerId,
+    staff: Staff,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    battlefun_instance
+        .set_banned(staff, &player_id, false)
+        .map_err(reject::custom)?;
+
+    eprintln!("{} unbanned {}", staff, player_id);
+    Ok(json(&GenericResponse::success()))
+}
+
+pub async fn set_role_handler(
+    player_id: PlayerId,
+    request: RoleRequest,
+    staff: Staff,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    battlefun_instance
+        .set_role(&player_id, request.role)
+        .map_err(reject::custom)?;
+
+    eprintln!("{} made {} {:?}", staff, player_id, request.role);
+    Ok(json(&GenericResponse::success()))
+}
+
+pub async fn announcement_handler(
+    request: AnnouncementRequest,
+    staff: Staff,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    eprintln!(
+        "{} announced ({:?}): {}",
+        staff, request.kind, request.message
+    );
+    battlefun_instance.announce(request.kind, request.message);
+
+    Ok(json(&GenericResponse::success()))
+}
@@ -0,0 +1,103 @@
+//! Authentication of REST requests, which carry a session token in an
+//! `Authorization: Bearer <token>` header.
+
+use warp::{reject, Filter, Rejection};
+
+use crate::{battlefun, error::Error, with_battlefun_instance, BattleFunInstance};
+use battlefun::{
+    admin::{Role, Staff},
+    PlayerId, PlayerToken,
+};
+
+/// Extracts the bearer token, without checking it.
+pub fn with_token() -> impl Filter<Extract = (PlayerToken,), Error = Rejection> + Copy {
+    warp::header::optional::<String>("authorization").and_then(
+        |header: Option<String>| async move {
+            bearer_token(header.as_deref()).map_err(reject::custom)
+        },
+    )
+}
+
+/// Extracts the player the bearer token was issued to, rejecting requests whose token is missing,
+/// invalid, expired or revoked.
+pub fn with_player(
+    battlefun_instance: BattleFunInstance,
+) -> impl Filter<Extract = (PlayerId,), Error = Rejection> + Clone {
+    with_token()
+        .and(with_battlefun_instance(battlefun_instance))
+        .and_then(
+            |token: PlayerToken, battlefun_instance: BattleFunInstance| async move {
+                battlefun_instance
+                    .authenticate(&token)
+                    .map(|claims| claims.sub)
+                    .map_err(reject::custom)
+            },
+        )
+}
+
+/// Extracts who is using the admin API, rejecting requests from anyone without at least `role`.
+pub fn with_staff(
+    battlefun_instance: BattleFunInstance,
+    role: Role,
+) -> impl Filter<Extract = (Staff,), Error = Rejection> + Clone {
+    with_token()
+        .and(with_battlefun_instance(battlefun_instance))
+        .and_then(
+            move |token: PlayerToken, battlefun_instance: BattleFunInstance| async move {
+                battlefun_instance
+                    .authenticate_staff(&token, role)
+                    .map_err(reject::custom)
+            },
+        )
+}
+
+fn bearer_token(header: Option<&str>) -> Result<PlayerToken, Error> {
+    let header = match header {
+        Some(header) => header.trim(),
+        None => {
+            return Err(Error::Unauthorized(
+                "missing authorization header".to_owned(),
+            ))
+        }
+    };
+
+    match header.split_once(' ') {
+        Some((scheme, token))
+            if scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty() =>
+        {
+            Ok(token.trim().to_owned())
+        }
+        _ => Err(Error::Unauthorized("expected a bearer token".to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bearer_tokens() {
+        for header in &[
+            "Bearer abc.def.ghi",
+            "bearer abc.def.ghi",
+            "BEARER   abc.def.ghi ",
+        ] {
+            assert_eq!(bearer_token(Some(header)).unwrap(), "abc.def.ghi");
+        }
+
+        for header in &[
+            "",
+            "Bearer",
+            "Bearer ",
+            "B",
+            "Basic dXNlcjpwYXNz",
+            "abc.def.ghi",
+        ] {
+            assert!(matches!(
+                bearer_token(Some(header)),
+                Err(Error::Unauthorized(_))
+            ));
+        }
+        assert!(bearer_token(None).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+//! Who may moderate the server, and what they are shown of it.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{gamemaster::GameInfo, GameId, Player, PlayerId};
+
+/// What a player may do besides playing. Each role may do everything the ones before it may.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Player,
+    /// May use the admin API, except to change roles.
+    Moderator,
+    Admin,
+}
+
+impl Default for Role {
+    fn default() -> Self {
+        Role::Player
+    }
+}
+
+/// Someone using the admin API: the operator, who holds `ADMIN_TOKEN` and may do anything, or a
+/// player with a role.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Staff {
+    Operator,
+    Player(PlayerId, Role),
+}
+
+impl Staff {
+    pub fn role(self) -> Role {
+        match self {
+            Staff::Operator => Role::Admin,
+            Staff::Player(_, role) => role,
+        }
+    }
+
+    /// Whether they may moderate a player with `role`: the operator may moderate anyone, and
+    /// everyone else only players whose role is lower than theirs.
+    pub fn outranks(self, role: Role) -> bool {
+        match self {
+            Staff::Operator => true,
+            Staff::Player(_, own) => own > role,
+        }
+    }
+}
+
+impl fmt::Display for Staff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Staff::Operator => write!(f, "operator"),
+            Staff::Player(id, role) => write!(f, "{:?} {}", role, id),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct PlayerSummary {
+    pub player_id: PlayerId,
+    pub name: String,
+    pub rating: f64,
+    pub role: Role,
+    pub banned: bool,
+    pub bot: bool,
+    pub connected: bool,
+    pub authenticated: bool,
+}
+
+impl PlayerSummary {
+    pub fn new(player: &Player) -> Self {
+        Self {
+            player_id: player.id,
+            name: player.name.clone(),
+            rating: player.rating.value,
+            role: player.role,
+            banned: player.banned,
+            bot: player.bot.is_some(),
+            connected: player.connection.outbox.is_some(),
+            authenticated: player.connection.authenticated,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct GameSummary {
+    pub game_id: GameId,
+    pub player1_id: PlayerId,
+    pub player2_id: PlayerId,
+    pub finished: bool,
+    pub spectators: usize,
+}
+
+impl GameSummary {
+    pub fn new(game_id: GameId, game_info: &GameInfo, spectators: usize) -> Self {
+        Self {
+            game_id,
+            player1_id: game_info.player1_id,
+            player2_id: game_info.player2_id,
+            finished: game_info.finished,
+            spectators,
+        }
+    }
+}
@@ -0,0 +1,361 @@
+//! Games that have ended, kept so that their players can look back on them and share them.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use super::gamemaster::GameInfo;
+use super::proto::from_game_fn::GameUpdate;
+use super::stats::{self, Tally};
+use super::store::{seconds_since_epoch, ArchiveStore, Record};
+use super::{CellIndex, FromBattleFunProto, GameId, PlayerId, ShipPlacement};
+use crate::error::Error;
+use crate::game;
+
+/// Everything about a finished game, enough to play it back shot by shot.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArchivedGame {
+    pub game_id: GameId,
+    pub player1_id: PlayerId,
+    pub player2_id: PlayerId,
+    pub player1_ships: ShipPlacement,
+    pub player2_ships: ShipPlacement,
+    /// Every shot, in the order they were taken.
+    pub moves: Vec<Move>,
+    pub winner_id: PlayerId,
+    /// Whether the loser resigned rather than losing their last ship.
+    pub resigned: bool,
+    /// Whether the loser ran out of time rather than losing their last ship.
+    pub timed_out: bool,
+    /// Seconds since the Unix epoch.
+    pub started_at: u64,
+    pub finished_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Move {
+    pub player_id: PlayerId,
+    pub cell: CellIndex,
+    pub hit: bool,
+    /// The ship the shot sank, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sunk: Option<String>,
+}
+
+/// An archived game without its ships and moves, for listing a player's games.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ArchivedGameSummary {
+    pub game_id: GameId,
+    pub player1_id: PlayerId,
+    pub player2_id: PlayerId,
+    pub winner_id: PlayerId,
+    pub resigned: bool,
+    pub timed_out: bool,
+    pub moves: usize,
+    pub started_at: u64,
+    pub finished_at: u64,
+}
+
+impl ArchivedGame {
+    /// Archives the game as it ended in `game_update`, now.
+    pub fn new(
+        game_id: GameId,
+        game_info: &GameInfo,
+        game_update: &GameUpdate,
+        winner_id: PlayerId,
+    ) -> Self {
+        let moves = game::moves(game_update)
+            .into_iter()
+            .filter_map(|played| {
+                Some(Move {
+                    player_id: PlayerId::parse_str(&played.player_id).ok()?,
+                    cell: played.cell as CellIndex,
+                    hit: played.outcome.hit,
+                    sunk: played.outcome.sunk,
+                })
+            })
+            .collect();
+
+        let player1_placement = game_update.player1_placement.clone().unwrap_or_default();
+        let player2_placement = game_update.player2_placement.clone().unwrap_or_default();
+        let (loser_placement, winner_shots) = if winner_id == game_info.player1_id {
+            (&player2_placement, &game_update.player1_shots)
+        } else {
+            (&player1_placement, &game_update.player2_shots)
+        };
+        let timed_out = game_info.timed_out.is_some();
+        let resigned = !timed_out
+            && game::destroyed_ships(loser_placement, winner_shots).len()
+                < loser_placement.ships.len();
+
+        Self {
+            game_id,
+            player1_id: game_info.player1_id,
+            player2_id: game_info.player2_id,
+            player1_ships: player1_placement.from_proto(),
+            player2_ships: player2_placement.from_proto(),
+            moves,
+            winner_id,
+            resigned,
+            timed_out,
+            started_at: seconds_since_epoch(game_info.started_at),
+            finished_at: seconds_since_epoch(SystemTime::now()),
+        }
+    }
+
+    pub fn has_player(&self, player_id: PlayerId) -> bool {
+        player_id == self.player1_id || player_id == self.player2_id
+    }
+
+    pub fn summary(&self) -> ArchivedGameSummary {
+        ArchivedGameSummary {
+            game_id: self.game_id,
+            player1_id: self.player1_id,
+            player2_id: self.player2_id,
+            winner_id: self.winner_id,
+            resigned: self.resigned,
+            timed_out: self.timed_out,
+            moves: self.moves.len(),
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+        }
+    }
+}
+
+impl Record for ArchivedGame {
+    type Key = GameId;
+
+    fn key(&self) -> GameId {
+        self.game_id
+    }
+}
+
+/// Every archived game, kept in the archive store and indexed by its players.
+pub struct Archive {
+    store: Arc<ArchiveStore>,
+    index: Mutex<Index>,
+}
+
+#[derive(Default)]
+struct Index {
+    games: HashMap<GameId, ArchivedGame>,
+    by_player: HashMap<PlayerId, Vec<GameId>>,
+}
+
+impl Index {
+    fn insert(&mut self, game: ArchivedGame) {
+        for player_id in [game.player1_id, game.player2_id] {
+            self.by_player
+                .entry(player_id)
+                .or_default()
+                .push(game.game_id);
+        }
+        self.games.insert(game.game_id, game);
+    }
+}
+
+impl Archive {
+    /// Loads every game saved in `store`.
+    pub fn new(store: Arc<ArchiveStore>) -> Result<Self, Error> {
+        let mut index = Index::default();
+        for game in store.load()? {
+            index.insert(game);
+        }
+
+        Ok(Self {
+            store,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// Saves the game, unless it was archived already.
+    pub fn add(&self, game: ArchivedGame) -> Result<(), Error> {
+        let mut index = self.index.lock().unwrap();
+        if index.games.contains_key(&game.game_id) {
+            return Ok(());
+        }
+
+        self.store.save(&game)?;
+        index.insert(game);
+
+        Ok(())
+    }
+
+    pub fn get(&self, game_id: &GameId) -> Option<ArchivedGame> {
+        self.index.lock().unwrap().games.get(game_id).cloned()
+    }
+
+    /// The games the player played, latest first.
+    pub fn games_of(&self, player_id: &PlayerId) -> Vec<ArchivedGameSummary> {
+        let mut summaries: Vec<_> = self
+            .played_by(player_id)
+            .iter()
+            .map(ArchivedGame::summary)
+            .collect();
+
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.finished_at));
+        summaries
+    }
+
+    /// Every game the player played, in no particular order.
+    pub fn played_by(&self, player_id: &PlayerId) -> Vec<ArchivedGame> {
+        let index = self.index.lock().unwrap();
+        index
+            .by_player
+            .get(player_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|game_id| index.games.get(game_id).cloned())
+            .collect()
+    }
+
+    /// The wins and losses of every player over the games finished since `start`, in seconds
+    /// since the Unix epoch.
+    pub fn tally_since(&self, start: u64) -> HashMap<PlayerId, Tally> {
+        let index = self.index.lock().unwrap();
+        stats::tally(
+            index
+                .games
+                .values()
+                .filter(|game| game.finished_at >= start),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::battlefun::proto::{self, to_game_fn::CreateGame, to_game_fn::Turn};
+    use crate::battlefun::store::InMemoryStore;
+
+    fn placement(cells: &[i64]) -> proto::ShipPlacement {
+        proto::ShipPlacement {
+            ships: vec![proto::Ship {
+                r#type: "patrol_boat".to_owned(),
+                cells: cells.to_vec(),
+            }],
+        }
+    }
+
+    /// A game player 1 wins, either by sinking player 2's only ship or because player 2 resigns
+    /// after a shot each.
+    fn finished_game(game_info: &GameInfo, resign: bool) -> GameUpdate {
+        let mut game = game::create(&CreateGame {
+            game_id: GameId::new_v4().to_string(),
+            player1_id: game_info.player1_id.to_string(),
+            player2_id: game_info.player2_id.to_string(),
+            player1_placement: Some(placement(&[0, 1])),
+            player2_placement: Some(placement(&[10, 11])),
+        });
+
+        let shots = [
+            (game_info.player1_id, 10),
+            (game_info.player2_id, 5),
+            (game_info.player1_id, 11),
+        ];
+        let played = if resign { 2 } else { 3 };
+        for &(player_id, shot) in &shots[..played] {
+            let turn = Turn {
+                game_id: game.game_id.clone(),
+                player_id: player_id.to_string(),
+                shot,
+            };
+            game = game::apply(&game, &turn).unwrap();
+        }
+
+        if resign {
+            game.status = proto::GameStatus::Player1Win as i32;
+        }
+        game
+    }
+
+    #[test]
+    fn archives_games_with_their_moves() {
+        let game_info = GameInfo::new(PlayerId::new_v4(), PlayerId::new_v4());
+        let update = finished_game(&game_info, false);
+
+        let game = ArchivedGame::new(GameId::new_v4(), &game_info, &update, game_info.player1_id);
+        assert!(!game.resigned);
+        assert_eq!(
+            game.moves,
+            vec![
+                Move {
+                    player_id: game_info.player1_id,
+                    cell: 10,
+                    hit: true,
+                    sunk: None,
+                },
+                Move {
+                    player_id: game_info.player2_id,
+                    cell: 5,
+                    hit: false,
+                    sunk: None,
+                },
+                Move {
+                    player_id: game_info.player1_id,
+                    cell: 11,
+                    hit: true,
+                    sunk: Some("patrol_boat".to_owned()),
+                },
+            ]
+        );
+
+        let resigned = finished_game(&game_info, true);
+        let game = ArchivedGame::new(
+            GameId::new_v4(),
+            &game_info,
+            &resigned,
+            game_info.player1_id,
+        );
+        assert!(game.resigned);
+        assert!(!game.timed_out);
+        assert_eq!(game.moves.len(), 2);
+
+        let timed_out = GameInfo {
+            timed_out: Some(game_info.player2_id),
+            ..game_info.clone()
+        };
+        let game = ArchivedGame::new(
+            GameId::new_v4(),
+            &timed_out,
+            &resigned,
+            game_info.player1_id,
+        );
+        assert!(game.timed_out);
+        assert!(!game.resigned);
+    }
+
+    #[test]
+    fn lists_each_players_games() {
+        let store = Arc::new(InMemoryStore::new());
+        let archive = Archive::new(store.clone()).unwrap();
+        let (alice, bob, carol) = (PlayerId::new_v4(), PlayerId::new_v4(), PlayerId::new_v4());
+
+        let archived = |player1_id, player2_id, finished_at| {
+            let game_info = GameInfo::new(player1_id, player2_id);
+            let mut game = ArchivedGame::new(
+                GameId::new_v4(),
+                &game_info,
+                &finished_game(&game_info, true),
+                player1_id,
+            );
+            game.finished_at = finished_at;
+            archive.add(game.clone()).unwrap();
+            game
+        };
+        let first = archived(alice, bob, 100);
+        let second = archived(carol, alice, 200);
+        archive.add(first.clone()).unwrap();
+
+        let games: Vec<GameId> = archive.games_of(&alice).iter().map(|g| g.game_id).collect();
+        assert_eq!(games, vec![second.game_id, first.game_id]);
+        assert_eq!(archive.games_of(&bob).len(), 1);
+        assert_eq!(archive.get(&first.game_id), Some(first));
+
+        let reloaded = Archive::new(store).unwrap();
+        assert_eq!(reloaded.games_of(&alice).len(), 2);
+    }
+}
@@ -1,73 +1,968 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use serde_json;
-use tokio::sync::RwLock;
-use warp::ws::Message;
+use tokio::sync::oneshot;
+use tokio::time;
 
-use super::gamemaster::GameMaster;
-use super::kafka::StatefunKafkaClient;
+use super::admin::{GameSummary, PlayerSummary, Role, Staff};
+use super::archive::{Archive, ArchivedGame};
+use super::bot::Difficulty;
+use super::clock::Alarm;
+use super::fleet::FleetSpec;
+use super::gamemaster::{GameInfo, GameMaster};
+use super::lobby::{ChallengeId, Lobbies, LobbyCode};
 use super::matchmaking::Matchmaker;
+use super::outbox::{Outbox, OutboxConfig, OutboxMetrics};
+use super::protocol::{AnnouncementKind, ErrorMessage, ServerMessage, MAX_CHAT_LENGTH};
+use super::rating::Rating;
+use super::requests::PendingRequests;
+use super::session::{Claims, Sessions};
+use super::shard::ShardedMap;
+use super::stats::{self, LeaderboardEntry, Period, PlayerStats};
+use super::store::{ArchiveStore, GameStore, PlayerRecord, PlayerStore};
+use super::topics::{Subscriptions, Topic};
+use super::transport::GameEngineTransport;
 use super::{
-    proto::{GameStatus, ShipPlacement, Shot},
-    FromBattleFunProto, GameId, Player, PlayerGameState, PlayerId, PlayerToken,
+    proto::{
+        from_game_fn::{Failure, GameUpdate, Response},
+        FromGameFn, GameStatus,
+    },
+    CellIndex, FromBattleFunProto, GameId, Player, PlayerConnection, PlayerGameState, PlayerId,
+    PlayerToken, RequestId, ShipPlacement, SpectatorGameState,
 };
+use crate::config::Config;
+use crate::error::Error;
+use crate::game::{self, FailureCode};
 
+/// The state shared by every request. Each part synchronizes itself, and no lock is held across an
+/// `.await`, so requests for unrelated players and games proceed concurrently.
 pub struct BattleFun {
-    pub players: HashMap<PlayerId, Player>,
-    pub player_tokens: HashMap<PlayerToken, PlayerId>,
-    pub statefun_kafka_client: Arc<RwLock<StatefunKafkaClient>>,
-    pub gamemaster: Arc<RwLock<GameMaster>>,
+    pub players: ShardedMap<PlayerId, Player>,
+    pub transport: Arc<dyn GameEngineTransport>,
+    store: Arc<PlayerStore>,
+    pub sessions: Sessions,
+    admin_token: Option<String>,
+    pub requests: Arc<PendingRequests>,
+    pub gamemaster: Arc<GameMaster>,
+    pub archive: Archive,
     pub matchmaker: Matchmaker,
+    pub lobbies: Lobbies,
+    pub subscriptions: Subscriptions,
+    pub fleet: FleetSpec,
+    pub turn_result_timeout: Duration,
+    pub outbox_config: OutboxConfig,
+    pub outbox_metrics: Arc<OutboxMetrics>,
+    /// The player each bot plays as.
+    pub bots: HashMap<Difficulty, PlayerId>,
+    bot_move_delay: Duration,
 }
 
 impl BattleFun {
-    pub fn new() -> Self {
-        let statefun_kafka_client = Arc::new(RwLock::new(StatefunKafkaClient::new(
-            "kafka-broker:9092",
-            "to-statefun".to_owned(),
-        )));
-        let gamemaster = Arc::new(RwLock::new(GameMaster::new(statefun_kafka_client.clone())));
-
-        Self {
-            players: HashMap::new(),
-            player_tokens: HashMap::new(),
-            statefun_kafka_client: statefun_kafka_client.clone(),
+    /// Sets up the game, with every player saved in `store` registered, every game saved in
+    /// `game_store` carrying on and every game in `archive_store` on record.
+    pub fn new(
+        transport: Arc<dyn GameEngineTransport>,
+        store: Arc<PlayerStore>,
+        game_store: Arc<GameStore>,
+        archive_store: Arc<ArchiveStore>,
+        config: &Config,
+    ) -> Result<Self, Error> {
+        let requests = Arc::new(PendingRequests::new());
+        let gamemaster = Arc::new(GameMaster::new(
+            transport.clone(),
+            requests.clone(),
+            game_store,
+            config.clock.clone(),
+        ));
+
+        let restored = gamemaster.restore()?;
+        if restored > 0 {
+            eprintln!("Restored {} games in progress", restored);
+        }
+
+        let players = ShardedMap::new();
+        for record in store.load()? {
+            players.insert(record.id, record.into_player());
+        }
+
+        // Bots are saved like everyone else, but only to keep their ratings. No token is ever
+        // issued for them.
+        let mut bots = HashMap::new();
+        for &difficulty in &Difficulty::ALL {
+            let id = difficulty.player_id();
+            let saved = players.remove(&id);
+
+            let bot = Player {
+                id,
+                name: format!("Bot ({})", difficulty.name()),
+                created_at: saved
+                    .as_ref()
+                    .map_or_else(SystemTime::now, |saved| saved.created_at),
+                rating: saved.map(|saved| saved.rating).unwrap_or_default(),
+                revocations: Default::default(),
+                role: Role::Player,
+                banned: false,
+                bot: Some(difficulty),
+                connection: PlayerConnection {
+                    outbox: None,
+                    authenticated: false,
+                },
+            };
+
+            bots.insert(difficulty, bot.id);
+            players.insert(bot.id, bot);
+        }
+
+        Ok(Self {
+            players,
+            transport,
+            store,
+            sessions: Sessions::new(config.token_secret.as_deref(), config.token_ttl),
+            admin_token: config.admin_token.clone(),
+            requests,
             gamemaster: gamemaster.clone(),
-            matchmaker: Matchmaker::new(gamemaster.clone()),
+            archive: Archive::new(archive_store)?,
+            matchmaker: Matchmaker::new(
+                gamemaster.clone(),
+                config.queue_timeout,
+                config.rating_window.clone(),
+            ),
+            lobbies: Lobbies::new(gamemaster.clone(), config.queue_timeout),
+            subscriptions: Subscriptions::new(),
+            fleet: config.fleet.clone(),
+            turn_result_timeout: config.turn_result_timeout,
+            outbox_config: config.outbox.clone(),
+            outbox_metrics: Arc::new(OutboxMetrics::default()),
+            bots,
+            bot_move_delay: config.bot_move_delay,
+        })
+    }
+
+    /// Registers the player, replacing any earlier registration of theirs, and saves them.
+    pub fn register(&self, player: Player) -> Result<(), Error> {
+        self.store.save(&PlayerRecord::from_player(&player))?;
+
+        self.players.insert(player.id, player);
+
+        Ok(())
+    }
+
+    /// Registers an existing player again, renaming them if `name` is given. Everything else
+    /// about them stays as it was, their connection included.
+    pub fn reregister(&self, player_id: &PlayerId, name: Option<String>) -> Result<Player, Error> {
+        let player = self
+            .players
+            .update(player_id, |player| {
+                if let Some(name) = name {
+                    player.name = name;
+                }
+                player.clone()
+            })
+            .ok_or(Error::NoSuchPlayer(*player_id))?;
+
+        self.store.save(&PlayerRecord::from_player(&player))?;
+
+        Ok(player)
+    }
+
+    /// Forgets the player, here and in the player store, which also stops every token issued to
+    /// them from working. They give up their place in the queue, their lobbies and challenges, and
+    /// every game they are still playing, and their WebSocket is closed.
+    pub fn deregister(&self, player_id: &PlayerId) -> Result<(), Error> {
+        let player = match self.players.get(player_id) {
+            Some(player) => player,
+            None => return Err(Error::NoSuchPlayer(*player_id)),
+        };
+
+        self.withdraw(&player);
+        self.players.remove(player_id);
+
+        self.store.remove(player_id)
+    }
+
+    /// Takes the player out of the queue, their lobbies, challenges and games, and disconnects
+    /// them.
+    fn withdraw(&self, player: &Player) {
+        self.matchmaker.cancel(&player.id);
+        self.lobbies.forget(player.id);
+
+        let mut games = Vec::new();
+        self.gamemaster.games.for_each(|game_id, game_info| {
+            if !game_info.finished {
+                games.extend(game_info.opponent_of(player.id).map(|id| (*game_id, id)));
+            }
+        });
+        for (game_id, opponent_id) in games {
+            self.forfeit(player, game_id, opponent_id);
+        }
+
+        if let Some(outbox) = &player.connection.outbox {
+            outbox.close();
+        }
+    }
+
+    /// Resigns the game for a player who is leaving. The result is settled here, because the
+    /// engine reports it only once the player is gone. If the engine cannot be told, the game is
+    /// abandoned instead, since nobody would be left to resign it later.
+    fn forfeit(&self, player: &Player, game_id: GameId, opponent_id: PlayerId) {
+        if let Err(e) = self.gamemaster.resign(game_id, player.id) {
+            eprintln!(
+                "Game {}: could not resign for {}, abandoning it: {}",
+                game_id, player.id, e
+            );
+            let _ = self.abandon_game(game_id);
+            return;
+        }
+
+        if let Some(opponent) = self.players.get(&opponent_id) {
+            if self.gamemaster.finish(game_id) {
+                self.rate_result(player, &opponent, opponent_id);
+                self.game_finished(game_id, opponent_id);
+            }
+        }
+    }
+
+    /// Saves the player's current record to the player store.
+    fn save_player(&self, player_id: &PlayerId) {
+        let record = match self.players.with(player_id, PlayerRecord::from_player) {
+            Some(record) => record,
+            None => return,
+        };
+
+        if let Err(e) = self.store.save(&record) {
+            eprintln!("could not save player {}: {}", player_id, e);
+        }
+    }
+
+    pub fn new_outbox(&self) -> Outbox {
+        Outbox::new(self.outbox_config.clone(), self.outbox_metrics.clone())
+    }
+
+    /// Whether `outbox` is the connection the player authenticated, rather than another one
+    /// opened with their id.
+    pub fn is_authenticated(&self, player_id: &PlayerId, outbox: &Outbox) -> bool {
+        self.players
+            .with(player_id, |p| {
+                p.connection.authenticated
+                    && p.connection
+                        .outbox
+                        .as_ref()
+                        .map_or(false, |current| current.is_same(outbox))
+            })
+            .unwrap_or(false)
+    }
+
+    /// Checks that the token was issued here to a registered player, and has neither expired nor
+    /// been revoked.
+    pub fn authenticate(&self, token: &str) -> Result<Claims, Error> {
+        let claims = self.sessions.verify(token)?;
+
+        match self
+            .players
+            .with(&claims.sub, |p| (p.revocations.allows(&claims), p.banned))
+        {
+            Some((true, false)) => Ok(claims),
+            Some((true, true)) => Err(Error::Forbidden("banned".to_owned())),
+            _ => Err(Error::Unauthorized("invalid or expired token".to_owned())),
+        }
+    }
+
+    /// Checks that the token is `ADMIN_TOKEN`, or a token of a player with at least `role`.
+    pub fn authenticate_staff(&self, token: &str, role: Role) -> Result<Staff, Error> {
+        if let Some(admin_token) = &self.admin_token {
+            if constant_time_eq(token.as_bytes(), admin_token.as_bytes()) {
+                return Ok(Staff::Operator);
+            }
+        }
+
+        let claims = self.authenticate(token)?;
+        let staff = match self.players.with(&claims.sub, |p| p.role) {
+            Some(player_role) => Staff::Player(claims.sub, player_role),
+            None => return Err(Error::NoSuchPlayer(claims.sub)),
+        };
+
+        if staff.role() < role {
+            return Err(Error::Forbidden(format!("requires the {:?} role", role)));
+        }
+
+        Ok(staff)
+    }
+
+    pub fn player_summaries(&self) -> Vec<PlayerSummary> {
+        let mut summaries = Vec::new();
+        self.players
+            .for_each(|_, player| summaries.push(PlayerSummary::new(player)));
+
+        summaries
+    }
+
+    pub fn game_summaries(&self) -> Vec<GameSummary> {
+        let mut summaries = Vec::new();
+        self.gamemaster.games.for_each(|game_id, game_info| {
+            let spectators = self.subscriptions.count(Topic::Game(*game_id));
+            summaries.push(GameSummary::new(*game_id, game_info, spectators));
+        });
+
+        summaries
+    }
+
+    /// Closes the player's WebSocket. They may connect again.
+    pub fn kick(&self, staff: Staff, player_id: &PlayerId) -> Result<(), Error> {
+        let player = self.moderated_player(staff, player_id)?;
+        if let Some(outbox) = player.connection.outbox {
+            outbox.close();
+        }
+
+        Ok(())
+    }
+
+    /// Bans or unbans the player. A banned player is withdrawn from everything they are doing, as
+    /// if they had deregistered, and their tokens stop working until they are unbanned.
+    pub fn set_banned(
+        &self,
+        staff: Staff,
+        player_id: &PlayerId,
+        banned: bool,
+    ) -> Result<(), Error> {
+        let player = self.moderated_player(staff, player_id)?;
+        if player.bot.is_some() {
+            return Err(Error::InvalidArgument("cannot ban a bot".to_owned()));
+        }
+
+        self.players.update(player_id, |p| p.banned = banned);
+        self.save_player(player_id);
+
+        if banned {
+            self.withdraw(&player);
+        }
+
+        Ok(())
+    }
+
+    /// Looks up a player for `staff` to moderate, who must outrank them.
+    fn moderated_player(&self, staff: Staff, player_id: &PlayerId) -> Result<Player, Error> {
+        let player = match self.players.get(player_id) {
+            Some(player) => player,
+            None => return Err(Error::NoSuchPlayer(*player_id)),
+        };
+
+        if !staff.outranks(player.role) {
+            return Err(Error::Forbidden(format!(
+                "cannot moderate a player with the {:?} role",
+                player.role
+            )));
+        }
+
+        Ok(player)
+    }
+
+    pub fn set_role(&self, player_id: &PlayerId, role: Role) -> Result<(), Error> {
+        match self.players.update(player_id, |p| p.role = role) {
+            Some(()) => {
+                self.save_player(player_id);
+                Ok(())
+            }
+            None => Err(Error::NoSuchPlayer(*player_id)),
+        }
+    }
+
+    /// Ends the game without a result, telling both players.
+    pub fn abandon_game(&self, game_id: GameId) -> Result<(), Error> {
+        let game_info = match self.gamemaster.abandon(game_id) {
+            Some(game_info) => game_info,
+            None => return Err(Error::NoSuchGame(game_id)),
+        };
+
+        let message = ServerMessage::GameAbandoned { game_id };
+        self.send(&game_info.player1_id, &message);
+        self.send(&game_info.player2_id, &message);
+        self.subscriptions.close(Topic::Game(game_id), &message);
+        self.subscriptions.publish(Topic::Lobby, &message);
+
+        Ok(())
+    }
+
+    /// Sends the announcement to everyone following announcements.
+    pub fn announce(&self, kind: AnnouncementKind, message: String) {
+        let announcement = ServerMessage::Announcement { kind, message };
+        self.subscriptions
+            .publish(Topic::Announcements, &announcement);
+    }
+
+    /// Issues a new token for the player.
+    pub fn issue_token(&self, player_id: &PlayerId) -> Result<(PlayerToken, Claims), Error> {
+        match self.players.with(player_id, |p| p.revocations.generation) {
+            Some(generation) => Ok(self.sessions.issue(*player_id, generation)),
+            None => Err(Error::NoSuchPlayer(*player_id)),
         }
     }
 
-    pub async fn player_authenticated(&self, player_id: &PlayerId) {
-        let games = &self.gamemaster.read().await.games;
-        let matching_game = games.iter().find(|(_, game_info)| {
-            return *player_id == game_info.player1_id || *player_id == game_info.player2_id;
+    /// Swaps a valid token for a new one, revoking the old one.
+    pub fn refresh_token(&self, token: &str) -> Result<(PlayerToken, Claims), Error> {
+        let claims = self.authenticate(token)?;
+
+        self.players
+            .update(&claims.sub, |p| p.revocations.revoke(&claims));
+        self.save_player(&claims.sub);
+
+        self.issue_token(&claims.sub)
+    }
+
+    /// Revokes the token, or every token issued to its player if `all` is set.
+    pub fn revoke_token(&self, token: &str, all: bool) -> Result<(), Error> {
+        let claims = self.authenticate(token)?;
+
+        self.players.update(&claims.sub, |p| {
+            if all {
+                p.revocations.revoke_all();
+            } else {
+                p.revocations.revoke(&claims);
+            }
         });
+        self.save_player(&claims.sub);
 
-        if let Some((game_id, _)) = matching_game {
-            self.statefun_kafka_client
-                .write()
-                .await
-                .send_get_game_status(game_id)
-                .await
-                .expect("uh-oh");
+        Ok(())
+    }
+
+    /// Sends `message` to `player_id` if they are connected.
+    pub fn send(&self, player_id: &PlayerId, message: &ServerMessage) {
+        self.players
+            .with(player_id, |player| player.connection.send(message));
+    }
+
+    /// Validates the player's ships and queues them for a game, telling both players once they
+    /// have been matched.
+    pub fn queue_for_game(
+        &self,
+        player_id: PlayerId,
+        ships: ShipPlacement,
+        bot_fallback: Option<Difficulty>,
+    ) -> Result<(), Error> {
+        self.fleet
+            .validate(&ships)
+            .map_err(Error::InvalidShipPlacement)?;
+
+        let rating = match self.players.with(&player_id, |p| p.rating.value) {
+            Some(rating) => rating,
+            None => return Ok(()),
+        };
+        let is_connected = |id: &PlayerId| self.players.with(id, |p| p.connection.outbox.is_some());
+
+        if let Some((game_id, game_info)) =
+            self.matchmaker
+                .play(player_id, ships, rating, bot_fallback, is_connected)?
+        {
+            self.game_started(game_id, game_info);
         }
+
+        Ok(())
     }
 
-    pub fn handle_game_update(
+    /// Takes the player's shot at `cell`, which must be on the board.
+    pub fn take_shot(
         &self,
+        player_id: PlayerId,
         game_id: GameId,
-        player1_id: PlayerId,
-        player1_placement: ShipPlacement,
-        player2_id: PlayerId,
-        player2_placement: ShipPlacement,
-        status: i32,
-        player1_shots: Vec<Shot>,
-        player2_shots: Vec<Shot>,
+        cell: CellIndex,
+    ) -> Result<oneshot::Receiver<Response>, Error> {
+        if !self.fleet.contains(cell) {
+            return Err(Error::InvalidArgument(format!(
+                "cell {} is not on the board",
+                cell
+            )));
+        }
+
+        self.gamemaster.turn(game_id, player_id, cell)
+    }
+
+    /// Starts a game between the player and the bot of `difficulty`, which places its ships at
+    /// random.
+    pub fn play_bot(
+        &self,
+        player_id: PlayerId,
+        ships: ShipPlacement,
+        difficulty: Difficulty,
+    ) -> Result<GameId, Error> {
+        self.fleet
+            .validate(&ships)
+            .map_err(Error::InvalidShipPlacement)?;
+
+        let bot_id = self.bots[&difficulty];
+        let bot_ships = match self.fleet.random_placement(&mut rand::thread_rng()) {
+            Some(ships) => ships,
+            None => {
+                return Err(Error::InvalidArgument(
+                    "the fleet does not fit on the board".to_owned(),
+                ))
+            }
+        };
+
+        let game_id = self
+            .gamemaster
+            .start_game(player_id, ships, bot_id, bot_ships)?;
+        self.game_started(game_id, GameInfo::new(player_id, bot_id));
+
+        Ok(game_id)
+    }
+
+    /// Takes the player out of the matchmaking queue.
+    pub fn leave_queue(&self, player_id: &PlayerId) -> Result<(), Error> {
+        if self.matchmaker.cancel(player_id) {
+            Ok(())
+        } else {
+            Err(Error::NotQueued)
+        }
+    }
+
+    /// Opens a private lobby hosted by the player, returning the code others can join it with.
+    pub fn create_lobby(
+        &self,
+        player_id: PlayerId,
+        ships: ShipPlacement,
+    ) -> Result<LobbyCode, Error> {
+        self.fleet
+            .validate(&ships)
+            .map_err(Error::InvalidShipPlacement)?;
+
+        Ok(self.lobbies.create(player_id, ships))
+    }
+
+    /// Starts a game between the player and the host of the lobby `code`.
+    pub fn join_lobby(
+        &self,
+        player_id: PlayerId,
+        code: &str,
+        ships: ShipPlacement,
+    ) -> Result<GameId, Error> {
+        self.fleet
+            .validate(&ships)
+            .map_err(Error::InvalidShipPlacement)?;
+
+        let (game_id, game_info) = self.lobbies.join(code, player_id, ships)?;
+        self.game_started(game_id, game_info);
+
+        Ok(game_id)
+    }
+
+    /// Challenges `opponent_id` to a game, prompting them to accept or decline it.
+    pub fn challenge(
+        &self,
+        player_id: PlayerId,
+        opponent_id: PlayerId,
+        ships: ShipPlacement,
+    ) -> Result<ChallengeId, Error> {
+        if opponent_id == player_id {
+            return Err(Error::InvalidArgument(
+                "cannot challenge yourself".to_owned(),
+            ));
+        }
+        if self.bots.values().any(|&bot_id| bot_id == opponent_id) {
+            return Err(Error::InvalidArgument(
+                "bots do not take challenges".to_owned(),
+            ));
+        }
+        if !self.players.contains_key(&opponent_id) {
+            return Err(Error::NoSuchPlayer(opponent_id));
+        }
+        self.fleet
+            .validate(&ships)
+            .map_err(Error::InvalidShipPlacement)?;
+
+        let (challenger_name, challenger_rating) = match self
+            .players
+            .with(&player_id, |p| (p.name.clone(), p.rating.value))
+        {
+            Some(challenger) => challenger,
+            None => return Err(Error::NoSuchPlayer(player_id)),
+        };
+
+        let challenge_id = self.lobbies.challenge(player_id, opponent_id, ships);
+        self.send(
+            &opponent_id,
+            &ServerMessage::ChallengeReceived {
+                challenge_id,
+                challenger_id: player_id,
+                challenger_name,
+                challenger_rating,
+            },
+        );
+
+        Ok(challenge_id)
+    }
+
+    /// Starts the game the player was challenged to.
+    pub fn accept_challenge(
+        &self,
+        player_id: PlayerId,
+        challenge_id: ChallengeId,
+        ships: ShipPlacement,
+    ) -> Result<GameId, Error> {
+        self.fleet
+            .validate(&ships)
+            .map_err(Error::InvalidShipPlacement)?;
+
+        let (game_id, game_info) = self.lobbies.accept(challenge_id, player_id, ships)?;
+        self.game_started(game_id, game_info);
+
+        Ok(game_id)
+    }
+
+    /// Turns down the challenge, telling the challenger so.
+    pub fn decline_challenge(
+        &self,
+        player_id: PlayerId,
+        challenge_id: ChallengeId,
+    ) -> Result<(), Error> {
+        let challenge = self.lobbies.decline(challenge_id, player_id)?;
+        self.send(
+            &challenge.challenger_id,
+            &ServerMessage::ChallengeDeclined {
+                challenge_id,
+                player_id,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Takes players who have waited too long out of the matchmaking queue, telling them so, and
+    /// matches the rest whose rating windows have widened enough. Also closes lobbies and
+    /// challenges nobody took up.
+    pub fn sweep_queue(&self) {
+        self.lobbies.expire();
+
+        for expired in self.matchmaker.expire() {
+            let player_id = expired.id;
+            let played_bot = match expired.bot_fallback {
+                Some(difficulty) => match self.play_bot(player_id, expired.ships, difficulty) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        eprintln!("could not start bot game for {}: {}", player_id, e);
+                        false
+                    }
+                },
+                None => false,
+            };
+
+            if !played_bot {
+                self.send(&player_id, &ServerMessage::QueueExpired);
+            }
+        }
+
+        for (game_id, game_info) in self.matchmaker.pair_waiting() {
+            self.game_started(game_id, game_info);
+        }
+    }
+
+    /// Warns players who are running out of time, and resigns the games of those who have run
+    /// out. The engine then reports the result like any other resignation.
+    pub fn check_clocks(&self) {
+        for (game_id, alarm) in self.gamemaster.check_clocks() {
+            match alarm {
+                Alarm::Warning {
+                    player_id,
+                    remaining,
+                } => self.send(
+                    &player_id,
+                    &ServerMessage::ClockWarning {
+                        game_id,
+                        remaining_ms: remaining.as_millis() as u64,
+                    },
+                ),
+                Alarm::Expired { player_id } => self.time_expired(game_id, player_id),
+            }
+        }
+    }
+
+    /// Resigns the game for the player, who ran out of time, and tells both players. If the game
+    /// engine cannot be told, the clock sounds again on the next check.
+    fn time_expired(&self, game_id: GameId, player_id: PlayerId) {
+        match self.gamemaster.time_out(game_id, player_id) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                eprintln!(
+                    "Game {}: could not resign for {}, who ran out of time: {}",
+                    game_id, player_id, e
+                );
+                return;
+            }
+        }
+
+        eprintln!("Game {}: player {} ran out of time", game_id, player_id);
+
+        let message = ServerMessage::TimeExpired { game_id, player_id };
+        self.send(&player_id, &message);
+        if let Some(opponent_id) = self
+            .gamemaster
+            .games
+            .with(&game_id, |game_info| game_info.opponent_of(player_id))
+            .flatten()
+        {
+            self.send(&opponent_id, &message);
+        }
+    }
+
+    fn game_started(&self, game_id: GameId, game_info: GameInfo) {
+        self.match_found(game_id, game_info.player1_id, game_info.player2_id);
+        self.match_found(game_id, game_info.player2_id, game_info.player1_id);
+
+        self.subscriptions.publish(
+            Topic::Lobby,
+            &ServerMessage::GameStarted {
+                game_id,
+                player1_id: game_info.player1_id,
+                player2_id: game_info.player2_id,
+            },
+        );
+    }
+
+    fn match_found(&self, game_id: GameId, player_id: PlayerId, opponent_id: PlayerId) {
+        let (opponent_name, opponent_rating) = match self
+            .players
+            .with(&opponent_id, |p| (p.name.clone(), p.rating.value))
+        {
+            Some(opponent) => opponent,
+            None => return,
+        };
+
+        self.send(
+            &player_id,
+            &ServerMessage::MatchFound {
+                game_id,
+                opponent_id,
+                opponent_name,
+                opponent_rating,
+            },
+        );
+    }
+
+    /// Relays a chat message from `player_id` to both players of `game_id`.
+    pub fn chat(&self, player_id: PlayerId, game_id: GameId, message: String) -> Result<(), Error> {
+        if message.chars().count() > MAX_CHAT_LENGTH {
+            return Err(Error::InvalidArgument(format!(
+                "chat messages may be at most {} characters",
+                MAX_CHAT_LENGTH
+            )));
+        }
+
+        let game_info = match self.gamemaster.games.get(&game_id) {
+            Some(game_info) => game_info,
+            None => return Err(Error::NoSuchGame(game_id)),
+        };
+        if !game_info.has_player(player_id) {
+            return Err(Error::InvalidArgument("Invalid player".to_owned()));
+        }
+
+        let name = match self.players.with(&player_id, |p| p.name.clone()) {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let chat = ServerMessage::Chat {
+            game_id,
+            player_id,
+            name,
+            message,
+        };
+
+        self.send(&game_info.player1_id, &chat);
+        self.send(&game_info.player2_id, &chat);
+
+        Ok(())
+    }
+
+    /// Tells the opponents of `player_id` that they came online or went offline.
+    pub fn announce_presence(&self, player_id: PlayerId, online: bool) {
+        let presence = ServerMessage::Presence { player_id, online };
+
+        let mut opponents = Vec::new();
+        self.gamemaster.games.for_each(|_, game_info| {
+            opponents.extend(game_info.opponent_of(player_id));
+        });
+
+        for opponent_id in opponents {
+            self.send(&opponent_id, &presence);
+        }
+    }
+
+    /// Subscribes `outbox` to the topic. A game's subscribers are sent where it stands now to
+    /// begin with.
+    pub fn subscribe(&self, topic: Topic, outbox: Outbox) -> Result<(), Error> {
+        if let Topic::Game(game_id) = topic {
+            if !self.gamemaster.games.contains_key(&game_id) {
+                return Err(Error::NoSuchGame(game_id));
+            }
+        }
+
+        if self.subscriptions.subscribe(topic, outbox) {
+            if let Topic::Game(game_id) = topic {
+                self.gamemaster.request_status(game_id)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Subscribes the player's WebSocket to each of the topics.
+    pub fn player_subscribe(&self, player_id: PlayerId, topics: Vec<Topic>) -> Result<(), Error> {
+        let outbox = match self.outbox_of(&player_id) {
+            Some(outbox) => outbox,
+            None => return Err(Error::NoSuchPlayer(player_id)),
+        };
+
+        topics
+            .into_iter()
+            .try_for_each(|topic| self.subscribe(topic, outbox.clone()))
+    }
+
+    pub fn player_unsubscribe(&self, player_id: PlayerId, topics: Vec<Topic>) {
+        if let Some(outbox) = self.outbox_of(&player_id) {
+            for topic in topics {
+                self.subscriptions.unsubscribe(topic, &outbox);
+            }
+        }
+    }
+
+    fn outbox_of(&self, player_id: &PlayerId) -> Option<Outbox> {
+        self.players
+            .with(player_id, |player| player.connection.outbox.clone())
+            .flatten()
+    }
+
+    /// Asks the game engine for every game the player is still in, so that a player who
+    /// reconnects, even to a restarted backend, is sent the state of each of them. The player
+    /// also starts following announcements.
+    pub fn player_authenticated(&self, player_id: &PlayerId) {
+        let mut matching_games = Vec::new();
+        self.gamemaster.games.for_each(|game_id, game_info| {
+            if game_info.has_player(*player_id) && !game_info.finished {
+                matching_games.push(*game_id);
+            }
+        });
+
+        if let Some(outbox) = self.outbox_of(player_id) {
+            self.subscriptions.subscribe(Topic::Announcements, outbox);
+        }
+
+        for game_id in matching_games {
+            let (request_id, _) = self.requests.register(*player_id);
+            if let Err(e) = self.transport.get_game_status(request_id, game_id) {
+                eprintln!(
+                    "Game {}: could not ask for status for {}: {}",
+                    game_id, player_id, e
+                );
+            }
+        }
+    }
+
+    pub fn handle_statefun_message(&self, message: FromGameFn) {
+        let game_id = match GameId::parse_str(&message.game_id) {
+            Ok(id) => id,
+            Err(e) => {
+                eprintln!("Got message for invalid game id {}: {}", message.game_id, e);
+                return;
+            }
+        };
+
+        let request = match (RequestId::parse_str(&message.request_id), &message.response) {
+            (Ok(request_id), Some(response)) => self
+                .requests
+                .complete(&request_id, response)
+                .map(|player_id| (request_id, player_id)),
+            _ => None,
+        };
+
+        match message.response {
+            Some(Response::GameUpdate(game_update)) => {
+                self.handle_game_update(game_id, game_update)
+            }
+            Some(Response::Failure(failure)) => {
+                eprintln!(
+                    "Game {}: got failure {:?}: {}",
+                    game_id,
+                    FailureCode::from_code(failure.code),
+                    failure.failure_description
+                );
+
+                if let Some((request_id, player_id)) = request {
+                    self.handle_failure(game_id, request_id, player_id, failure);
+                }
+            }
+            None => eprintln!("Game {}: got empty message from statefun", game_id),
+        }
+    }
+
+    /// Tells `player_id` that the command they sent as `request_id` failed.
+    fn handle_failure(
+        &self,
+        game_id: GameId,
+        request_id: RequestId,
+        player_id: PlayerId,
+        failure: Failure,
     ) {
-        let player1 = self.players.get(&player1_id).expect("unknown player1");
-        let player2 = self.players.get(&player2_id).expect("unknown player2");
-        let real_status = GameStatus::from_i32(status).unwrap();
+        let error = ErrorMessage::rejected(game_id, request_id, failure);
+
+        self.send(&player_id, &ServerMessage::Error(error));
+    }
+
+    pub fn handle_game_update(&self, game_id: GameId, game_update: GameUpdate) {
+        // Abandoned and archived games are no longer played here, whatever the engine says.
+        if !self.gamemaster.games.contains_key(&game_id) {
+            eprintln!("Game {}: update for a game no longer played", game_id);
+            return;
+        }
+
+        let (player1_id, player2_id) = match (
+            PlayerId::parse_str(&game_update.player1_id),
+            PlayerId::parse_str(&game_update.player2_id),
+        ) {
+            (Ok(player1_id), Ok(player2_id)) => (player1_id, player2_id),
+            _ => {
+                eprintln!("Game {}: update has invalid player ids", game_id);
+                return;
+            }
+        };
+
+        // Either player may have deregistered since; the other is still sent the game.
+        let (player1, player2) = (self.players.get(&player1_id), self.players.get(&player2_id));
+        if player1.is_none() && player2.is_none() {
+            eprintln!("Game {}: update for unknown players", game_id);
+            return;
+        }
+
+        let winner_id = game::winner_id(&game_update).and_then(|id| PlayerId::parse_str(id).ok());
+        if let Some(winner_id) = winner_id {
+            if self.gamemaster.finish(game_id) {
+                if let (Some(player1), Some(player2)) = (&player1, &player2) {
+                    self.rate_result(player1, player2, winner_id);
+                }
+                self.game_finished(game_id, winner_id);
+            }
+            self.archive_game(game_id, &game_update, winner_id);
+        }
+
+        let status = game_update.status;
+        let player1_placement = game_update.player1_placement.unwrap_or_default();
+        let player2_placement = game_update.player2_placement.unwrap_or_default();
+        let player1_shots = game_update.player1_shots;
+        let player2_shots = game_update.player2_shots;
+        let real_status = GameStatus::from_i32(status).unwrap_or(GameStatus::Unknown);
+
+        let to_move = match real_status {
+            GameStatus::Player1Turn => Some(player1_id),
+            GameStatus::Player2Turn => Some(player2_id),
+            _ => None,
+        };
+        let clock = self
+            .gamemaster
+            .run_clock(game_id, to_move)
+            .unwrap_or_default();
+        let now = Instant::now();
+        let millis = |left: Option<Duration>| left.map(|left| left.as_millis() as u64);
+        let turn_time_left_ms = millis(clock.turn_time_left(now));
+        let player1_time_left_ms = millis(clock.game_time_left(player1_id, now));
+        let player2_time_left_ms = millis(clock.game_time_left(player2_id, now));
 
         let player1_state = PlayerGameState {
             game_id,
@@ -76,8 +971,12 @@ impl BattleFun {
             your_turn: real_status == GameStatus::Player1Turn,
             your_shots: player1_shots.iter().map(|s| s.from_proto()).collect(),
             opponent_shots: player2_shots.iter().map(|s| s.from_proto()).collect(),
-            destroyed_opponent_ships: get_destroyed_ships(&player2_placement, &player1_shots),
+            destroyed_opponent_ships: game::destroyed_ships(&player2_placement, &player1_shots),
             your_ships: player1_placement.from_proto(),
+            winner_id,
+            your_time_left_ms: player1_time_left_ms,
+            opponent_time_left_ms: player2_time_left_ms,
+            turn_time_left_ms,
         };
 
         let player2_state = PlayerGameState {
@@ -87,37 +986,586 @@ impl BattleFun {
             your_turn: real_status == GameStatus::Player2Turn,
             your_shots: player2_shots.iter().map(|s| s.from_proto()).collect(),
             opponent_shots: player1_shots.iter().map(|s| s.from_proto()).collect(),
-            destroyed_opponent_ships: get_destroyed_ships(&player1_placement, &player2_shots),
+            destroyed_opponent_ships: game::destroyed_ships(&player1_placement, &player2_shots),
             your_ships: player2_placement.from_proto(),
+            winner_id,
+            your_time_left_ms: player2_time_left_ms,
+            opponent_time_left_ms: player1_time_left_ms,
+            turn_time_left_ms,
         };
 
-        if let Some(sender) = &player1.connection.sender {
-            let json = serde_json::to_string(&player1_state).unwrap();
-            let _ = sender.send(Ok(Message::text(json)));
+        let spectator_state = SpectatorGameState {
+            game_id,
+            player1_id,
+            player2_id,
+            current_state: status,
+            player1_shots: player1_shots.iter().map(|s| s.from_proto()).collect(),
+            player2_shots: player2_shots.iter().map(|s| s.from_proto()).collect(),
+            destroyed_player1_ships: game::destroyed_ships(&player1_placement, &player2_shots),
+            destroyed_player2_ships: game::destroyed_ships(&player2_placement, &player1_shots),
+            player1_ships: winner_id.map(|_| player1_placement.from_proto()),
+            player2_ships: winner_id.map(|_| player2_placement.from_proto()),
+            winner_id,
+        };
+
+        // Spectators are sent the final state and then let go.
+        let spectator_message = ServerMessage::SpectatorState {
+            game_state: spectator_state,
+        };
+        if winner_id.is_some() {
+            self.subscriptions
+                .close(Topic::Game(game_id), &spectator_message);
+        } else {
+            self.subscriptions
+                .publish(Topic::Game(game_id), &spectator_message);
         }
 
-        if let Some(sender) = &player2.connection.sender {
-            let json = serde_json::to_string(&player2_state).unwrap();
-            let _ = sender.send(Ok(Message::text(json)));
+        for (player, state) in [(player1, player1_state), (player2, player2_state)] {
+            let player = match player {
+                Some(player) => player,
+                None => continue,
+            };
+
+            if let Some(difficulty) = player.bot {
+                self.bot_move(difficulty, player.id, &state);
+            }
+
+            player
+                .connection
+                .send(&ServerMessage::GameState { game_state: state });
         }
     }
+
+    /// Has the bot `bot_id` take its shot, after a pause, if it is its turn.
+    fn bot_move(&self, difficulty: Difficulty, bot_id: PlayerId, state: &PlayerGameState) {
+        if !state.your_turn || state.winner_id.is_some() {
+            return;
+        }
+
+        let cell = match difficulty.choose_shot(&self.fleet, state, &mut rand::thread_rng()) {
+            Some(cell) => cell,
+            None => return,
+        };
+
+        let game_id = state.game_id;
+        let gamemaster = self.gamemaster.clone();
+        let delay = self.bot_move_delay;
+
+        tokio::task::spawn(async move {
+            time::delay_for(delay).await;
+
+            if let Err(e) = gamemaster.turn(game_id, bot_id, cell) {
+                eprintln!("Game {}: bot {} could not shoot: {}", game_id, bot_id, e);
+            }
+        });
+    }
+
+    /// Archives the finished game, and forgets it here once the archive has it.
+    fn archive_game(&self, game_id: GameId, game_update: &GameUpdate, winner_id: PlayerId) {
+        let game_info = match self.gamemaster.games.get(&game_id) {
+            Some(game_info) => game_info,
+            None => return,
+        };
+
+        let game = ArchivedGame::new(game_id, &game_info, game_update, winner_id);
+        match self.archive.add(game) {
+            Ok(()) => {
+                self.gamemaster.games.remove(&game_id);
+                self.publish_leaderboards();
+            }
+            Err(e) => eprintln!("Game {}: could not archive game: {}", game_id, e),
+        }
+    }
+
+    /// The player's record over the games in the archive.
+    pub fn player_stats(&self, player_id: &PlayerId) -> Result<PlayerStats, Error> {
+        let games = self.archive.played_by(player_id);
+        if games.is_empty() && !self.players.contains_key(player_id) {
+            return Err(Error::NoSuchPlayer(*player_id));
+        }
+
+        Ok(PlayerStats::new(*player_id, &games))
+    }
+
+    /// The first `limit` places of the leaderboard for the period, among the players of the games
+    /// in the archive that finished in it. Bots and banned players are left out.
+    pub fn leaderboard(&self, period: Period, limit: usize) -> Vec<LeaderboardEntry> {
+        let mut entries = Vec::new();
+        for (player_id, tally) in self.archive.tally_since(period.start()) {
+            let entry = self.players.with(&player_id, |player| {
+                if player.bot.is_some() || player.banned {
+                    return None;
+                }
+
+                Some(LeaderboardEntry {
+                    rank: 0,
+                    player_id,
+                    name: player.name.clone(),
+                    rating: player.rating.value,
+                    wins: tally.wins,
+                    losses: tally.losses,
+                })
+            });
+            entries.extend(entry.flatten());
+        }
+
+        stats::rank(period, entries, limit)
+    }
+
+    /// Sends the top of every leaderboard to its subscribers.
+    fn publish_leaderboards(&self) {
+        if self.subscriptions.count(Topic::Leaderboard) == 0 {
+            return;
+        }
+
+        for &period in &Period::ALL {
+            let entries = self.leaderboard(period, stats::PUSHED_PLACES);
+            self.subscriptions.publish(
+                Topic::Leaderboard,
+                &ServerMessage::Leaderboard { period, entries },
+            );
+        }
+    }
+
+    fn game_finished(&self, game_id: GameId, winner_id: PlayerId) {
+        self.subscriptions.publish(
+            Topic::Lobby,
+            &ServerMessage::GameFinished { game_id, winner_id },
+        );
+    }
+
+    /// Updates both players' ratings from the result of their game. Each rating is adjusted in
+    /// place, so results of other games finishing at the same time are not lost.
+    fn rate_result(&self, player1: &Player, player2: &Player, winner_id: PlayerId) {
+        let record = |player: &Player, opponent: &Rating| {
+            let won = player.id == winner_id;
+            let changed = self.players.update(&player.id, |p| {
+                p.rating.record(opponent, won);
+                ServerMessage::RatingChanged {
+                    player_id: p.id,
+                    name: p.name.clone(),
+                    rating: p.rating.value,
+                }
+            });
+            self.save_player(&player.id);
+
+            if let Some(changed) = changed {
+                self.subscriptions.publish(Topic::Leaderboard, &changed);
+            }
+        };
+
+        record(player1, &player2.rating);
+        record(player2, &player1.rating);
+    }
+}
+
+/// Compares secrets in time that does not depend on where they first differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
 }
 
-fn get_destroyed_ships(ship_placement: &ShipPlacement, shots: &Vec<Shot>) -> Vec<String> {
-    let shot_cells: Vec<i64> = shots.iter().map(|shot| shot.cell_id).collect();
-    let mut destroyed_ships = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::Value;
+    use tokio::sync::mpsc;
+
+    use crate::battlefun::clock::ClockConfig;
+    use crate::battlefun::matchmaking::RatingWindow;
+    use crate::battlefun::memory::InMemoryGameEngine;
+    use crate::battlefun::store::InMemoryStore;
+    use crate::battlefun::transport::UnreachableEngine;
+    use crate::config::{GameEngine, StoreKind};
 
-    for ship in &ship_placement.ships {
-        let is_destroyed = ship
-            .cells
+    /// A `BattleFun` playing on the in-memory engine, whose results are handled only when the
+    /// test settles them.
+    struct Fixture {
+        battlefun: BattleFun,
+        updates: mpsc::UnboundedReceiver<FromGameFn>,
+    }
+
+    impl Fixture {
+        fn new(clock: ClockConfig) -> Self {
+            Self::with_engine(clock, |sender| Arc::new(InMemoryGameEngine::new(sender)))
+        }
+
+        /// A fixture playing on the engine `engine` makes from the sender of engine results.
+        fn with_engine<F>(clock: ClockConfig, engine: F) -> Self
+        where
+            F: FnOnce(mpsc::UnboundedSender<FromGameFn>) -> Arc<dyn GameEngineTransport>,
+        {
+            let config = Config {
+                game_engine: GameEngine::InMemory,
+                player_store: StoreKind::InMemory,
+                game_store: StoreKind::InMemory,
+                archive_store: StoreKind::InMemory,
+                kafka_brokers: String::new(),
+                to_statefun_topic: String::new(),
+                from_statefun_topic: String::new(),
+                kafka_consumer_group: String::new(),
+                fleet: FleetSpec::default(),
+                turn_result_timeout: Duration::from_secs(1),
+                outbox: OutboxConfig {
+                    capacity: 64,
+                    eviction_deadline: Duration::from_secs(10),
+                },
+                queue_timeout: Duration::from_secs(60),
+                rating_window: RatingWindow {
+                    initial: 100.0,
+                    growth_per_sec: 0.0,
+                },
+                bot_move_delay: Duration::from_secs(0),
+                clock,
+                token_secret: Some("secret".to_owned()),
+                token_ttl: Duration::from_secs(60),
+                admin_token: None,
+            };
+
+            let (sender, updates) = mpsc::unbounded_channel();
+            let battlefun = BattleFun::new(
+                engine(sender),
+                Arc::new(InMemoryStore::new()),
+                Arc::new(InMemoryStore::new()),
+                Arc::new(InMemoryStore::new()),
+                &config,
+            )
+            .unwrap();
+
+            Self { battlefun, updates }
+        }
+
+        /// Handles everything the engine has answered so far.
+        fn settle(&mut self) {
+            while let Ok(message) = self.updates.try_recv() {
+                self.battlefun.handle_statefun_message(message);
+            }
+        }
+
+        /// Registers a player with `role`, connected and authenticated.
+        fn player(&self, role: Role) -> (PlayerId, Outbox) {
+            let id = PlayerId::new_v4();
+            let outbox = self.battlefun.new_outbox();
+            self.battlefun
+                .register(Player {
+                    id,
+                    name: "someone".to_owned(),
+                    created_at: SystemTime::now(),
+                    rating: Rating::default(),
+                    revocations: Default::default(),
+                    role,
+                    banned: false,
+                    bot: None,
+                    connection: PlayerConnection {
+                        outbox: Some(outbox.clone()),
+                        authenticated: true,
+                    },
+                })
+                .unwrap();
+
+            (id, outbox)
+        }
+
+        /// Matches two new players, the first of whom has the first turn, and settles the game's
+        /// creation.
+        fn game(&mut self) -> (GameId, (PlayerId, Outbox), (PlayerId, Outbox)) {
+            let (first, second) = (self.player(Role::Player), self.player(Role::Player));
+            self.battlefun
+                .queue_for_game(first.0, ships(), None)
+                .unwrap();
+            self.battlefun
+                .queue_for_game(second.0, ships(), None)
+                .unwrap();
+            self.settle();
+
+            let mut game_ids = Vec::new();
+            self.battlefun
+                .gamemaster
+                .games
+                .for_each(|game_id, game_info| {
+                    if game_info.has_player(first.0) {
+                        game_ids.push(*game_id);
+                    }
+                });
+
+            (game_ids[0], first, second)
+        }
+    }
+
+    fn ships() -> ShipPlacement {
+        vec![
+            ("carrier", vec![0, 1, 2, 3, 4]),
+            ("battleship", vec![10, 11, 12, 13]),
+            ("destroyer", vec![20, 21, 22]),
+            ("submarine", vec![30, 31, 32]),
+            ("patrol_boat", vec![40, 41]),
+        ]
+        .into_iter()
+        .map(|(name, cells)| (name.to_owned(), cells))
+        .collect()
+    }
+
+    /// Takes the messages waiting in the outbox.
+    fn messages(outbox: &Outbox) -> Vec<Value> {
+        outbox
+            .drained()
             .iter()
-            .map(|cell| shot_cells.contains(cell))
-            .all(|b| b);
+            .map(|text| serde_json::from_str(text).unwrap())
+            .collect()
+    }
 
-        if is_destroyed {
-            destroyed_ships.push(ship.r#type.clone());
+    fn of_type<'a>(messages: &'a [Value], r#type: &str) -> Vec<&'a Value> {
+        messages.iter().filter(|m| m["type"] == r#type).collect()
+    }
+
+    #[test]
+    fn deregistering_forfeits_games_and_leaves_the_queue() {
+        let mut fixture = Fixture::new(ClockConfig::default());
+        let (game_id, (leaver, leaver_outbox), (opponent, opponent_outbox)) = fixture.game();
+        let (waiting, _) = fixture.player(Role::Player);
+        let battlefun = &fixture.battlefun;
+        battlefun.queue_for_game(waiting, ships(), None).unwrap();
+        let (token, _) = battlefun.issue_token(&leaver).unwrap();
+        messages(&opponent_outbox);
+
+        battlefun.deregister(&leaver).unwrap();
+        assert!(!battlefun.players.contains_key(&leaver));
+        assert!(leaver_outbox.is_closed());
+        assert!(battlefun.authenticate(&token).is_err());
+
+        battlefun.deregister(&waiting).unwrap();
+        assert_eq!(battlefun.matchmaker.len(), 0);
+
+        fixture.settle();
+        let battlefun = &fixture.battlefun;
+        let archived = battlefun.archive.get(&game_id).unwrap();
+        assert_eq!(archived.winner_id, opponent);
+        assert!(archived.resigned);
+
+        let received = messages(&opponent_outbox);
+        let finished = of_type(&received, "game_state");
+        assert_eq!(
+            finished.last().unwrap()["game_state"]["winner_id"],
+            opponent.to_string()
+        );
+        assert!(matches!(
+            battlefun.deregister(&leaver),
+            Err(Error::NoSuchPlayer(_))
+        ));
+    }
+
+    #[test]
+    fn staff_moderate_only_players_ranked_below_them() {
+        let fixture = Fixture::new(ClockConfig::default());
+        let battlefun = &fixture.battlefun;
+        let (moderator, _) = fixture.player(Role::Moderator);
+        let (other_moderator, _) = fixture.player(Role::Moderator);
+        let (admin, _) = fixture.player(Role::Admin);
+        let (player, player_outbox) = fixture.player(Role::Player);
+        let staff = Staff::Player(moderator, Role::Moderator);
+
+        assert!(matches!(
+            battlefun.set_banned(staff, &admin, true),
+            Err(Error::Forbidden(_))
+        ));
+        assert!(matches!(
+            battlefun.kick(staff, &other_moderator),
+            Err(Error::Forbidden(_))
+        ));
+
+        let (token, _) = battlefun.issue_token(&player).unwrap();
+        battlefun.set_banned(staff, &player, true).unwrap();
+        assert!(player_outbox.is_closed());
+        assert!(matches!(
+            battlefun.authenticate(&token),
+            Err(Error::Forbidden(_))
+        ));
+        battlefun.set_banned(staff, &player, false).unwrap();
+        assert!(battlefun.authenticate(&token).is_ok());
+
+        battlefun.set_banned(Staff::Operator, &admin, true).unwrap();
+        battlefun
+            .set_banned(Staff::Operator, &admin, false)
+            .unwrap();
+
+        assert!(battlefun
+            .authenticate_staff(&token, Role::Moderator)
+            .is_err());
+        battlefun.set_role(&player, Role::Moderator).unwrap();
+        assert_eq!(
+            battlefun
+                .authenticate_staff(&token, Role::Moderator)
+                .unwrap(),
+            Staff::Player(player, Role::Moderator)
+        );
+    }
+
+    #[test]
+    fn abandons_games_it_cannot_resign_for_a_leaving_player() {
+        let fixture = Fixture::with_engine(ClockConfig::default(), |_| Arc::new(UnreachableEngine));
+        let battlefun = &fixture.battlefun;
+        let (leaving, _) = fixture.player(Role::Player);
+        let (staying, outbox) = fixture.player(Role::Player);
+        let game_id = GameId::new_v4();
+        battlefun
+            .gamemaster
+            .games
+            .insert(game_id, GameInfo::new(leaving, staying));
+
+        battlefun.deregister(&leaving).unwrap();
+
+        assert!(!battlefun.gamemaster.games.contains_key(&game_id));
+        assert!(battlefun.archive.get(&game_id).is_none());
+        assert_eq!(
+            battlefun.players.with(&staying, |p| p.rating.value),
+            Some(Rating::default().value)
+        );
+        assert_eq!(of_type(&messages(&outbox), "game_abandoned").len(), 1);
+    }
+
+    #[test]
+    fn ends_abandoned_games_in_the_engine_too() {
+        let mut fixture = Fixture::new(ClockConfig::default());
+        let (game_id, (first, first_outbox), (second, second_outbox)) = fixture.game();
+        messages(&first_outbox);
+        messages(&second_outbox);
+
+        fixture.battlefun.abandon_game(game_id).unwrap();
+        match fixture.updates.try_recv().unwrap().response.unwrap() {
+            Response::GameUpdate(update) => assert!(game::is_game_over(&update)),
+            other => panic!("expected a game update, got {:?}", other),
+        }
+
+        fixture.settle();
+        assert!(fixture.battlefun.archive.get(&game_id).is_none());
+        for (player_id, outbox) in &[(first, &first_outbox), (second, &second_outbox)] {
+            let received = messages(outbox);
+            assert_eq!(of_type(&received, "game_abandoned").len(), 1);
+            assert!(of_type(&received, "game_state").is_empty());
+            assert_eq!(
+                fixture
+                    .battlefun
+                    .players
+                    .with(player_id, |p| p.rating.value),
+                Some(Rating::default().value)
+            );
         }
     }
 
-    destroyed_ships
+    #[test]
+    fn reregistering_keeps_the_player_as_they_are() {
+        let fixture = Fixture::new(ClockConfig::default());
+        let battlefun = &fixture.battlefun;
+        let (player_id, outbox) = fixture.player(Role::Moderator);
+
+        let player = battlefun.reregister(&player_id, None).unwrap();
+        assert_eq!(player.name, "someone");
+        assert_eq!(player.role, Role::Moderator);
+        assert!(battlefun.is_authenticated(&player_id, &outbox));
+
+        let player = battlefun
+            .reregister(&player_id, Some("Ann".to_owned()))
+            .unwrap();
+        assert_eq!(player.name, "Ann");
+        assert!(battlefun.is_authenticated(&player_id, &outbox));
+    }
+
+    #[test]
+    fn hides_ships_from_spectators_until_the_game_ends() {
+        let mut fixture = Fixture::new(ClockConfig::default());
+        let (game_id, (first, _), _) = fixture.game();
+        let spectator = fixture.battlefun.new_outbox();
+
+        fixture
+            .battlefun
+            .subscribe(Topic::Game(game_id), spectator.clone())
+            .unwrap();
+        fixture.settle();
+        let received = messages(&spectator);
+        let state = &of_type(&received, "spectator_state")[0]["game_state"];
+        assert!(state["player1_ships"].is_null());
+        assert!(state["player2_ships"].is_null());
+
+        fixture.battlefun.take_shot(first, game_id, 0).unwrap();
+        fixture.settle();
+        let received = messages(&spectator);
+        let state = &of_type(&received, "spectator_state")[0]["game_state"];
+        assert_eq!(state["player1_shots"].as_array().unwrap().len(), 1);
+        assert!(state["player2_ships"].is_null());
+
+        fixture.battlefun.gamemaster.resign(game_id, first).unwrap();
+        fixture.settle();
+        let received = messages(&spectator);
+        let state = &of_type(&received, "spectator_state")[0]["game_state"];
+        assert_eq!(
+            state["player1_ships"]["carrier"],
+            serde_json::json!([0, 1, 2, 3, 4])
+        );
+        assert_eq!(
+            fixture.battlefun.subscriptions.count(Topic::Game(game_id)),
+            0
+        );
+    }
+
+    #[test]
+    fn routes_engine_failures_to_the_player_who_sent_the_command() {
+        let mut fixture = Fixture::new(ClockConfig::default());
+        let (game_id, (_, first_outbox), (second, second_outbox)) = fixture.game();
+        messages(&first_outbox);
+        messages(&second_outbox);
+
+        assert!(matches!(
+            fixture.battlefun.take_shot(second, game_id, 100),
+            Err(Error::InvalidArgument(_))
+        ));
+
+        let mut reply = fixture.battlefun.take_shot(second, game_id, 0).unwrap();
+        fixture.settle();
+        match reply.try_recv() {
+            Ok(Response::Failure(failure)) => {
+                assert_eq!(
+                    FailureCode::from_code(failure.code),
+                    Some(FailureCode::NotPlayersTurn)
+                )
+            }
+            other => panic!("expected a failure, got {:?}", other),
+        }
+
+        let received = messages(&second_outbox);
+        let errors = of_type(&received, "error");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["reason"], "game_rejected");
+        assert_eq!(errors[0]["failure"], "NOT_PLAYERS_TURN");
+        assert!(messages(&first_outbox).is_empty());
+    }
+
+    #[test]
+    fn forfeits_games_on_the_clock() {
+        let mut fixture = Fixture::new(ClockConfig {
+            turn_limit: Some(Duration::from_secs(0)),
+            game_limit: None,
+            warning: Duration::from_secs(0),
+        });
+        let (game_id, (first, first_outbox), (second, second_outbox)) = fixture.game();
+        messages(&first_outbox);
+        messages(&second_outbox);
+
+        fixture.battlefun.check_clocks();
+        fixture.battlefun.check_clocks();
+        for outbox in &[&first_outbox, &second_outbox] {
+            let received = messages(outbox);
+            let expired = of_type(&received, "time_expired");
+            assert_eq!(expired.len(), 1);
+            assert_eq!(expired[0]["player_id"], first.to_string());
+        }
+
+        fixture.settle();
+        let archived = fixture.battlefun.archive.get(&game_id).unwrap();
+        assert_eq!(archived.winner_id, second);
+        assert!(archived.timed_out);
+        assert!(!archived.resigned);
+
+        fixture.battlefun.check_clocks();
+        assert!(of_type(&messages(&second_outbox), "time_expired").is_empty());
+    }
 }
@@ -0,0 +1,284 @@
+//! Computer opponents, which choose their shots from the same `PlayerGameState` a human player
+//! is sent.
+
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
+
+use super::fleet::FleetSpec;
+use super::{CellIndex, PlayerGameState, PlayerId, Shot};
+
+/// How much more likely a ship is taken to lie across a cell for every unexplained hit it would
+/// also cover, which draws the search towards finishing off ships already found.
+const HIT_WEIGHT: u64 = 50;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    /// Shoots at random.
+    Easy,
+    /// Hunts on a checkerboard until it hits a ship, then targets the cells around the hit.
+    Medium,
+    /// Shoots where the remaining ships are most likely to be, given every shot so far.
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    /// The player this bot plays as, the same on every run so that its rating can be kept.
+    pub fn player_id(self) -> PlayerId {
+        PlayerId::from_u128(0xb07 << 16 | self as u128)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    /// Picks the next cell to shoot at, or `None` if every cell has been shot.
+    pub fn choose_shot(
+        self,
+        fleet: &FleetSpec,
+        state: &PlayerGameState,
+        rng: &mut impl Rng,
+    ) -> Option<CellIndex> {
+        let board = Board::new(fleet, state);
+
+        let candidates = match self {
+            Difficulty::Easy => board.unshot(),
+            Difficulty::Medium => board.hunt_or_target(),
+            Difficulty::Hard => board.most_likely(),
+        };
+
+        candidates.choose(rng).copied()
+    }
+}
+
+/// What a player knows of their opponent's board.
+struct Board<'a> {
+    fleet: &'a FleetSpec,
+    shots: Vec<Option<bool>>,
+    /// The lengths of the ships not yet sunk.
+    remaining: Vec<usize>,
+    /// Hits not accounted for by the ships already sunk.
+    unexplained_hits: usize,
+}
+
+impl<'a> Board<'a> {
+    fn new(fleet: &'a FleetSpec, state: &PlayerGameState) -> Self {
+        let mut shots = vec![None; fleet.board_width as usize * fleet.board_height as usize];
+        for &Shot { cell, hit } in &state.your_shots {
+            if let Some(shot) = shots.get_mut(cell as usize) {
+                *shot = Some(hit);
+            }
+        }
+
+        let (sunk, remaining): (Vec<_>, Vec<_>) = fleet
+            .ship_names()
+            .into_iter()
+            .partition(|(name, _)| state.destroyed_opponent_ships.contains(name));
+        let sunk_cells: usize = sunk.iter().map(|(_, length)| length).sum();
+        let remaining = remaining.into_iter().map(|(_, length)| length).collect();
+
+        let hits = shots.iter().filter(|&&shot| shot == Some(true)).count();
+
+        Self {
+            fleet,
+            shots,
+            remaining,
+            unexplained_hits: hits.saturating_sub(sunk_cells),
+        }
+    }
+
+    fn unshot(&self) -> Vec<CellIndex> {
+        self.cells()
+            .filter(|&cell| self.shot(cell).is_none())
+            .collect()
+    }
+
+    fn hunt_or_target(&self) -> Vec<CellIndex> {
+        if self.unexplained_hits > 0 {
+            let targets = self.targets();
+            if !targets.is_empty() {
+                return targets;
+            }
+        }
+
+        // Every ship covers at least one cell of a checkerboard whose squares are as far apart as
+        // the shortest ship left, so hunting on it alone finds them all.
+        let spacing = self.remaining.iter().copied().min().unwrap_or(1).max(1);
+        let width = self.fleet.board_width as usize;
+        let hunt: Vec<CellIndex> = self
+            .unshot()
+            .into_iter()
+            .filter(|&cell| (cell as usize / width + cell as usize % width) % spacing == 0)
+            .collect();
+
+        if hunt.is_empty() {
+            self.unshot()
+        } else {
+            hunt
+        }
+    }
+
+    /// Unshot cells next to hits, preferring those that continue a line of hits.
+    fn targets(&self) -> Vec<CellIndex> {
+        let mut in_line = Vec::new();
+        let mut adjacent = Vec::new();
+
+        for cell in self.cells().filter(|&cell| self.shot(cell) == Some(true)) {
+            for (dr, dc) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                let next = match self.neighbour(cell, *dr, *dc) {
+                    Some(next) if self.shot(next).is_none() => next,
+                    _ => continue,
+                };
+
+                let behind = self.neighbour(cell, -dr, -dc);
+                if behind.map(|b| self.shot(b)) == Some(Some(true)) {
+                    in_line.push(next);
+                } else {
+                    adjacent.push(next);
+                }
+            }
+        }
+
+        if in_line.is_empty() {
+            adjacent
+        } else {
+            in_line
+        }
+    }
+
+    /// The unshot cells the most placements of the remaining ships would cover.
+    fn most_likely(&self) -> Vec<CellIndex> {
+        let mut density = vec![0u64; self.shots.len()];
+
+        for &length in &self.remaining {
+            for line in self.fleet.lines(length) {
+                if line.iter().any(|&cell| self.shot(cell) == Some(false)) {
+                    continue;
+                }
+
+                let hits = line
+                    .iter()
+                    .filter(|&&cell| self.shot(cell) == Some(true))
+                    .count();
+                if hits > 0 && self.unexplained_hits == 0 {
+                    continue;
+                }
+
+                let weight = HIT_WEIGHT.pow(hits as u32);
+                for &cell in &line {
+                    density[cell as usize] += weight;
+                }
+            }
+        }
+
+        let unshot = self.unshot();
+        let best = unshot
+            .iter()
+            .map(|&cell| density[cell as usize])
+            .max()
+            .unwrap_or(0);
+
+        unshot
+            .into_iter()
+            .filter(|&cell| density[cell as usize] == best)
+            .collect()
+    }
+
+    fn cells(&self) -> impl Iterator<Item = CellIndex> {
+        (0..self.shots.len()).map(|cell| cell as CellIndex)
+    }
+
+    fn shot(&self, cell: CellIndex) -> Option<bool> {
+        self.shots[cell as usize]
+    }
+
+    fn neighbour(&self, cell: CellIndex, dr: i32, dc: i32) -> Option<CellIndex> {
+        let width = self.fleet.board_width as i32;
+        let height = self.fleet.board_height as i32;
+        let (row, column) = (cell as i32 / width + dr, cell as i32 % width + dc);
+
+        if row < 0 || row >= height || column < 0 || column >= width {
+            return None;
+        }
+
+        Some((row * width + column) as CellIndex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battlefun::{GameId, PlayerId, ShipPlacement};
+
+    fn state(shots: &[(CellIndex, bool)], destroyed: &[&str]) -> PlayerGameState {
+        PlayerGameState {
+            game_id: GameId::new_v4(),
+            opponent_id: PlayerId::new_v4(),
+            current_state: 0,
+            your_turn: true,
+            your_shots: shots
+                .iter()
+                .map(|&(cell, hit)| Shot { cell, hit })
+                .collect(),
+            opponent_shots: Vec::new(),
+            destroyed_opponent_ships: destroyed.iter().map(|&s| s.to_owned()).collect(),
+            your_ships: ShipPlacement::new(),
+            winner_id: None,
+            your_time_left_ms: None,
+            opponent_time_left_ms: None,
+            turn_time_left_ms: None,
+        }
+    }
+
+    fn choices(difficulty: Difficulty, state: &PlayerGameState) -> Vec<CellIndex> {
+        let fleet = FleetSpec::default();
+        let mut rng = rand::thread_rng();
+        let mut choices: Vec<CellIndex> = (0..200)
+            .filter_map(|_| difficulty.choose_shot(&fleet, state, &mut rng))
+            .collect();
+        choices.sort_unstable();
+        choices.dedup();
+        choices
+    }
+
+    #[test]
+    fn never_shoots_the_same_cell_twice() {
+        let shots: Vec<(CellIndex, bool)> = (0..99).map(|cell| (cell, false)).collect();
+
+        for &difficulty in &Difficulty::ALL {
+            assert_eq!(choices(difficulty, &state(&shots, &[])), vec![99]);
+        }
+
+        let shots: Vec<(CellIndex, bool)> = (0..100).map(|cell| (cell, false)).collect();
+        assert!(choices(Difficulty::Hard, &state(&shots, &[])).is_empty());
+    }
+
+    #[test]
+    fn targets_around_unexplained_hits() {
+        let around_44 = vec![34, 43, 45, 54];
+        assert_eq!(
+            choices(Difficulty::Medium, &state(&[(44, true)], &[])),
+            around_44
+        );
+
+        let in_line = state(&[(44, true), (45, true), (43, false)], &[]);
+        assert_eq!(choices(Difficulty::Medium, &in_line), vec![46]);
+        assert_eq!(choices(Difficulty::Hard, &in_line), vec![46]);
+    }
+
+    #[test]
+    fn hunts_once_hits_are_explained() {
+        let sunk = state(&[(0, true), (1, true), (2, false)], &["patrol_boat"]);
+
+        for &difficulty in &[Difficulty::Medium, Difficulty::Hard] {
+            let choices = choices(difficulty, &sunk);
+            assert!(!choices.contains(&10) && !choices.contains(&11));
+        }
+    }
+}
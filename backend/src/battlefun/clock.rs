@@ -0,0 +1,223 @@
+//! Clocks that limit how long players may take over their turns and their games.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::PlayerId;
+
+/// How long players may take. Without either limit, clocks never run out.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClockConfig {
+    /// How long a player may take over a single turn.
+    pub turn_limit: Option<Duration>,
+    /// How long a player may take over all of their turns in a game.
+    pub game_limit: Option<Duration>,
+    /// How long before running out a player is warned.
+    pub warning: Duration,
+}
+
+/// A warning or timeout raised by a clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alarm {
+    /// The player will run out of time soon.
+    Warning {
+        player_id: PlayerId,
+        remaining: Duration,
+    },
+    /// The player has run out of time and loses the game. Sounds on every check until the clock
+    /// is stopped, so that ending the game is tried again if it fails.
+    Expired { player_id: PlayerId },
+}
+
+/// The clock of a game, which runs for one player at a time: the player whose turn it is.
+#[derive(Debug, Clone, Default)]
+pub struct Clock {
+    config: ClockConfig,
+    /// The time each player has taken over their finished turns.
+    used: HashMap<PlayerId, Duration>,
+    running: Option<Running>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Running {
+    player_id: PlayerId,
+    since: Instant,
+    warned: bool,
+}
+
+impl Clock {
+    pub fn new(config: ClockConfig) -> Self {
+        Self {
+            config,
+            used: HashMap::new(),
+            running: None,
+        }
+    }
+
+    /// Whether the clock limits the players at all.
+    pub fn is_limited(&self) -> bool {
+        self.config.turn_limit.is_some() || self.config.game_limit.is_some()
+    }
+
+    /// Starts the player's turn, stopping the clock of the other player. Does nothing if it is
+    /// the player's turn already, so the same state may be reported any number of times.
+    pub fn start(&mut self, player_id: PlayerId, now: Instant) {
+        if self.running.map(|running| running.player_id) == Some(player_id) {
+            return;
+        }
+
+        self.stop(now);
+        self.running = Some(Running {
+            player_id,
+            since: now,
+            warned: false,
+        });
+    }
+
+    /// Stops the clock, charging the player whose turn it was for the time they took.
+    pub fn stop(&mut self, now: Instant) {
+        if let Some(running) = self.running.take() {
+            *self.used.entry(running.player_id).or_default() += now - running.since;
+        }
+    }
+
+    /// How much of the game limit the player has left, if there is one.
+    pub fn game_time_left(&self, player_id: PlayerId, now: Instant) -> Option<Duration> {
+        let limit = self.config.game_limit?;
+        let mut used = self.used.get(&player_id).copied().unwrap_or_default();
+        if let Some(running) = self.running.filter(|r| r.player_id == player_id) {
+            used += now - running.since;
+        }
+
+        Some(limit.checked_sub(used).unwrap_or_default())
+    }
+
+    /// How much of the turn limit the player whose turn it is has left, if there is one.
+    pub fn turn_time_left(&self, now: Instant) -> Option<Duration> {
+        let limit = self.config.turn_limit?;
+        let running = self.running?;
+
+        Some(limit.checked_sub(now - running.since).unwrap_or_default())
+    }
+
+    /// Sounds once when the player whose turn it is comes within the warning time of either
+    /// limit, and on every check once they have run out.
+    pub fn check(&mut self, now: Instant) -> Option<Alarm> {
+        let running = self.running?;
+        let remaining = match (
+            self.turn_time_left(now),
+            self.game_time_left(running.player_id, now),
+        ) {
+            (Some(turn), Some(game)) => turn.min(game),
+            (Some(left), None) | (None, Some(left)) => left,
+            (None, None) => return None,
+        };
+
+        if remaining == Duration::from_secs(0) {
+            return Some(Alarm::Expired {
+                player_id: running.player_id,
+            });
+        }
+
+        if !running.warned && remaining <= self.config.warning {
+            self.running = Some(Running {
+                warned: true,
+                ..running
+            });
+            return Some(Alarm::Warning {
+                player_id: running.player_id,
+                remaining,
+            });
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn warns_once_and_then_runs_out_each_turn() {
+        let mut clock = Clock::new(ClockConfig {
+            turn_limit: Some(secs(30)),
+            game_limit: None,
+            warning: secs(10),
+        });
+        let (alice, bob) = (PlayerId::new_v4(), PlayerId::new_v4());
+        let start = Instant::now();
+
+        clock.start(alice, start);
+        assert_eq!(clock.check(start + secs(15)), None);
+        clock.start(alice, start + secs(15));
+        assert_eq!(clock.turn_time_left(start + secs(15)), Some(secs(15)));
+
+        assert_eq!(
+            clock.check(start + secs(25)),
+            Some(Alarm::Warning {
+                player_id: alice,
+                remaining: secs(5)
+            })
+        );
+        assert_eq!(clock.check(start + secs(26)), None);
+
+        clock.start(bob, start + secs(29));
+        assert_eq!(clock.check(start + secs(40)), None);
+        assert!(matches!(
+            clock.check(start + secs(55)),
+            Some(Alarm::Warning { player_id, .. }) if player_id == bob
+        ));
+        assert_eq!(
+            clock.check(start + secs(59)),
+            Some(Alarm::Expired { player_id: bob })
+        );
+        assert_eq!(
+            clock.check(start + secs(60)),
+            Some(Alarm::Expired { player_id: bob })
+        );
+        assert_eq!(clock.game_time_left(bob, start + secs(60)), None);
+
+        clock.stop(start + secs(61));
+        assert_eq!(clock.check(start + secs(62)), None);
+    }
+
+    #[test]
+    fn charges_each_player_for_their_own_turns() {
+        let mut clock = Clock::new(ClockConfig {
+            turn_limit: None,
+            game_limit: Some(secs(60)),
+            warning: secs(0),
+        });
+        let (alice, bob) = (PlayerId::new_v4(), PlayerId::new_v4());
+        let start = Instant::now();
+
+        clock.start(alice, start);
+        clock.start(bob, start + secs(20));
+        clock.start(alice, start + secs(25));
+        assert_eq!(
+            clock.game_time_left(alice, start + secs(35)),
+            Some(secs(30))
+        );
+        assert_eq!(clock.game_time_left(bob, start + secs(35)), Some(secs(55)));
+        assert_eq!(clock.turn_time_left(start + secs(35)), None);
+
+        clock.stop(start + secs(40));
+        assert_eq!(clock.check(start + secs(100)), None);
+        assert_eq!(
+            clock.game_time_left(alice, start + secs(100)),
+            Some(secs(25))
+        );
+
+        clock.start(alice, start + secs(100));
+        assert_eq!(clock.check(start + secs(120)), None);
+        assert_eq!(
+            clock.check(start + secs(125)),
+            Some(Alarm::Expired { player_id: alice })
+        );
+    }
+}
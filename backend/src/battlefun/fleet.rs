@@ -0,0 +1,505 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use rand::{seq::SliceRandom, Rng};
+use serde::Serialize;
+
+use super::{CellIndex, ShipPlacement};
+
+/// How many times to start over placing a fleet at random before concluding it does not fit.
+const RANDOM_PLACEMENT_ATTEMPTS: usize = 100;
+
+/// Separates a ship's type from a suffix distinguishing several ships of the same type, e.g.
+/// `destroyer#1` and `destroyer#2`.
+const SHIP_SUFFIX_SEPARATOR: char = '#';
+
+/// The fleet played when none is configured.
+pub const DEFAULT_FLEET: &str = "carrier:5,battleship:4,destroyer:3,submarine:3,patrol_boat:2";
+
+/// The board and the ships every player must place on it.
+#[derive(Debug, Clone)]
+pub struct FleetSpec {
+    pub board_width: u8,
+    pub board_height: u8,
+    pub ships: Vec<ShipSpec>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShipSpec {
+    pub r#type: String,
+    pub length: usize,
+    pub count: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PlacementViolation {
+    /// The offending ship, or `None` for violations of the fleet as a whole.
+    pub ship: Option<String>,
+    #[serde(flatten)]
+    pub reason: ViolationReason,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ViolationReason {
+    UnknownShipType,
+    WrongLength {
+        expected: usize,
+        actual: usize,
+    },
+    CellOutOfBounds {
+        cell: CellIndex,
+    },
+    NotStraight,
+    NotContiguous,
+    Overlaps {
+        other: String,
+    },
+    WrongCount {
+        r#type: String,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl FleetSpec {
+    /// Parses a fleet such as `carrier:5,destroyer:3x2`: comma-separated ship types, each with a
+    /// length and an optional count.
+    pub fn parse(board_width: u8, board_height: u8, fleet: &str) -> Result<Self, String> {
+        if board_width == 0 || board_height == 0 {
+            return Err("board dimensions must be positive".to_owned());
+        }
+        if board_width as usize * board_height as usize > CellIndex::MAX as usize + 1 {
+            return Err(format!(
+                "a {}x{} board has more cells than can be addressed",
+                board_width, board_height
+            ));
+        }
+
+        let mut ships = Vec::new();
+
+        for entry in fleet.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (r#type, size) = split_once(entry, ':')
+                .ok_or_else(|| format!("expected <type>:<length>[x<count>], got {}", entry))?;
+            let (length, count) = split_once(size, 'x').unwrap_or((size, "1"));
+
+            let length = length
+                .parse()
+                .map_err(|_| format!("invalid length for {}: {}", r#type, length))?;
+            let count = count
+                .parse()
+                .map_err(|_| format!("invalid count for {}: {}", r#type, count))?;
+
+            if r#type.contains(SHIP_SUFFIX_SEPARATOR) {
+                return Err(format!(
+                    "ship type {} may not contain {}",
+                    r#type, SHIP_SUFFIX_SEPARATOR
+                ));
+            }
+            if length == 0 || count == 0 {
+                return Err(format!("{} must have a positive length and count", r#type));
+            }
+
+            ships.push(ShipSpec {
+                r#type: r#type.to_owned(),
+                length,
+                count,
+            });
+        }
+
+        if ships.is_empty() {
+            return Err("the fleet must contain at least one ship".to_owned());
+        }
+
+        Ok(Self {
+            board_width,
+            board_height,
+            ships,
+        })
+    }
+
+    /// Checks that `placement` places exactly this fleet on the board: every ship of a known type
+    /// and the right length, lying in a straight unbroken line, and not overlapping another.
+    pub fn validate(&self, placement: &ShipPlacement) -> Result<(), Vec<PlacementViolation>> {
+        let mut violations = Vec::new();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        let mut occupied: HashMap<CellIndex, &str> = HashMap::new();
+
+        let mut names: Vec<&String> = placement.keys().collect();
+        names.sort();
+
+        for name in names {
+            let cells = &placement[name];
+            let mut violation = |reason| {
+                violations.push(PlacementViolation {
+                    ship: Some(name.clone()),
+                    reason,
+                })
+            };
+
+            let r#type = ship_type(name);
+            let spec = match self.ships.iter().find(|spec| spec.r#type == r#type) {
+                Some(spec) => spec,
+                None => {
+                    violation(ViolationReason::UnknownShipType);
+                    continue;
+                }
+            };
+            *counts.entry(&spec.r#type).or_insert(0) += 1;
+
+            if cells.len() != spec.length {
+                violation(ViolationReason::WrongLength {
+                    expected: spec.length,
+                    actual: cells.len(),
+                });
+            }
+
+            let out_of_bounds: Vec<CellIndex> = cells
+                .iter()
+                .copied()
+                .filter(|&cell| !self.contains(cell))
+                .collect();
+            if !out_of_bounds.is_empty() {
+                for cell in out_of_bounds {
+                    violation(ViolationReason::CellOutOfBounds { cell });
+                }
+                continue;
+            }
+
+            if let Some(reason) = self.check_line(cells) {
+                violation(reason);
+            }
+
+            let mut overlapped: Vec<&str> = Vec::new();
+            for &cell in cells {
+                match occupied.get(&cell) {
+                    Some(other) if *other != name.as_str() => {
+                        if !overlapped.contains(other) {
+                            overlapped.push(other);
+                        }
+                    }
+                    _ => {
+                        occupied.insert(cell, name);
+                    }
+                }
+            }
+            for other in overlapped {
+                violation(ViolationReason::Overlaps {
+                    other: other.to_owned(),
+                });
+            }
+        }
+
+        for spec in &self.ships {
+            let actual = counts.get(spec.r#type.as_str()).copied().unwrap_or(0);
+            if actual != spec.count {
+                violations.push(PlacementViolation {
+                    ship: None,
+                    reason: ViolationReason::WrongCount {
+                        r#type: spec.r#type.clone(),
+                        expected: spec.count,
+                        actual,
+                    },
+                });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Every ship of the fleet with its length, named as a placement must name it.
+    pub fn ship_names(&self) -> Vec<(String, usize)> {
+        self.ships
+            .iter()
+            .flat_map(|spec| {
+                (1..=spec.count).map(move |i| {
+                    let name = if spec.count == 1 {
+                        spec.r#type.clone()
+                    } else {
+                        format!("{}{}{}", spec.r#type, SHIP_SUFFIX_SEPARATOR, i)
+                    };
+                    (name, spec.length)
+                })
+            })
+            .collect()
+    }
+
+    /// Every straight line of `length` cells on the board.
+    pub fn lines(&self, length: usize) -> Vec<Vec<CellIndex>> {
+        let (width, height) = (self.board_width as usize, self.board_height as usize);
+        let mut lines = Vec::new();
+
+        for row in 0..height {
+            for column in 0..width {
+                let cell = |row: usize, column: usize| (row * width + column) as CellIndex;
+
+                if column + length <= width {
+                    lines.push((column..column + length).map(|c| cell(row, c)).collect());
+                }
+                if length > 1 && row + length <= height {
+                    lines.push((row..row + length).map(|r| cell(r, column)).collect());
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// Places the fleet at random, or returns `None` if it does not fit on the board.
+    pub fn random_placement(&self, rng: &mut impl Rng) -> Option<ShipPlacement> {
+        let mut ships = self.ship_names();
+        ships.sort_by_key(|&(_, length)| Reverse(length));
+
+        'attempt: for _ in 0..RANDOM_PLACEMENT_ATTEMPTS {
+            let mut placement = ShipPlacement::new();
+            let mut occupied = vec![false; self.board_width as usize * self.board_height as usize];
+
+            for (name, length) in &ships {
+                let free: Vec<Vec<CellIndex>> = self
+                    .lines(*length)
+                    .into_iter()
+                    .filter(|line| line.iter().all(|&cell| !occupied[cell as usize]))
+                    .collect();
+
+                let line = match free.choose(rng) {
+                    Some(line) => line.clone(),
+                    None => continue 'attempt,
+                };
+                for &cell in &line {
+                    occupied[cell as usize] = true;
+                }
+                placement.insert(name.clone(), line);
+            }
+
+            return Some(placement);
+        }
+
+        None
+    }
+
+    /// Whether the cell is on the board.
+    pub fn contains(&self, cell: CellIndex) -> bool {
+        (cell as usize) < self.board_width as usize * self.board_height as usize
+    }
+
+    fn check_line(&self, cells: &[CellIndex]) -> Option<ViolationReason> {
+        let width = self.board_width as usize;
+        let mut positions: Vec<(usize, usize)> = cells
+            .iter()
+            .map(|&cell| (cell as usize / width, cell as usize % width))
+            .collect();
+        positions.sort();
+
+        let same_row = positions.windows(2).all(|w| w[0].0 == w[1].0);
+        let same_column = positions.windows(2).all(|w| w[0].1 == w[1].1);
+
+        let contiguous = if same_row {
+            positions.windows(2).all(|w| w[1].1 == w[0].1 + 1)
+        } else if same_column {
+            positions.windows(2).all(|w| w[1].0 == w[0].0 + 1)
+        } else {
+            return Some(ViolationReason::NotStraight);
+        };
+
+        if contiguous {
+            None
+        } else {
+            Some(ViolationReason::NotContiguous)
+        }
+    }
+}
+
+impl Default for FleetSpec {
+    fn default() -> Self {
+        Self::parse(10, 10, DEFAULT_FLEET).unwrap()
+    }
+}
+
+fn ship_type(name: &str) -> &str {
+    split_once(name, SHIP_SUFFIX_SEPARATOR)
+        .map(|(r#type, _)| r#type)
+        .unwrap_or(name)
+}
+
+fn split_once(value: &str, separator: char) -> Option<(&str, &str)> {
+    let index = value.find(separator)?;
+    Some((&value[..index], &value[index + separator.len_utf8()..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placement(ships: &[(&str, &[CellIndex])]) -> ShipPlacement {
+        ships
+            .iter()
+            .map(|(name, cells)| ((*name).to_owned(), cells.to_vec()))
+            .collect()
+    }
+
+    fn classic() -> ShipPlacement {
+        placement(&[
+            ("carrier", &[0, 1, 2, 3, 4]),
+            ("battleship", &[10, 20, 30, 40]),
+            ("destroyer", &[97, 98, 99]),
+            ("submarine", &[55, 65, 75]),
+            ("patrol_boat", &[8, 9]),
+        ])
+    }
+
+    fn reasons(result: Result<(), Vec<PlacementViolation>>) -> Vec<ViolationReason> {
+        result
+            .unwrap_err()
+            .into_iter()
+            .map(|violation| violation.reason)
+            .collect()
+    }
+
+    #[test]
+    fn accepts_valid_fleet() {
+        assert_eq!(FleetSpec::default().validate(&classic()), Ok(()));
+    }
+
+    #[test]
+    fn knows_which_cells_are_on_the_board() {
+        let fleet = FleetSpec::parse(8, 6, DEFAULT_FLEET).unwrap();
+        assert!(fleet.contains(0));
+        assert!(fleet.contains(47));
+        assert!(!fleet.contains(48));
+    }
+
+    #[test]
+    fn places_fleets_at_random() {
+        let mut rng = rand::thread_rng();
+
+        let fleet = FleetSpec::parse(6, 6, "carrier:5,destroyer:3x2,patrol_boat:2x3").unwrap();
+        for _ in 0..20 {
+            let placement = fleet.random_placement(&mut rng).unwrap();
+            assert_eq!(fleet.validate(&placement), Ok(()));
+        }
+
+        let crowded = FleetSpec::parse(3, 3, "carrier:5").unwrap();
+        assert_eq!(crowded.random_placement(&mut rng), None);
+    }
+
+    #[test]
+    fn rejects_empty_and_unknown_ships() {
+        let fleet = FleetSpec::default();
+
+        let violations = fleet.validate(&placement(&[])).unwrap_err();
+        assert_eq!(violations.len(), 5);
+        assert!(violations.iter().all(|v| v.ship.is_none()));
+
+        let mut ships = classic();
+        ships.insert("foo".to_owned(), vec![3, 4, 5]);
+        assert_eq!(
+            fleet.validate(&ships).unwrap_err(),
+            vec![PlacementViolation {
+                ship: Some("foo".to_owned()),
+                reason: ViolationReason::UnknownShipType,
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_length() {
+        let mut ships = classic();
+        ships.insert("patrol_boat".to_owned(), vec![8]);
+
+        assert_eq!(
+            reasons(FleetSpec::default().validate(&ships)),
+            vec![ViolationReason::WrongLength {
+                expected: 2,
+                actual: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_cells_off_the_board() {
+        let mut ships = classic();
+        ships.insert("patrol_boat".to_owned(), vec![99, 100]);
+
+        assert_eq!(
+            reasons(FleetSpec::default().validate(&ships)),
+            vec![ViolationReason::CellOutOfBounds { cell: 100 }]
+        );
+    }
+
+    #[test]
+    fn rejects_diagonal_and_broken_ships() {
+        let fleet = FleetSpec::default();
+
+        let mut ships = classic();
+        ships.insert("submarine".to_owned(), vec![55, 66, 77]);
+        assert_eq!(
+            reasons(fleet.validate(&ships)),
+            vec![ViolationReason::NotStraight]
+        );
+
+        let mut ships = classic();
+        ships.insert("submarine".to_owned(), vec![55, 65, 85]);
+        assert_eq!(
+            reasons(fleet.validate(&ships)),
+            vec![ViolationReason::NotContiguous]
+        );
+
+        // Cells 59 and 60 are adjacent indices but on different rows.
+        let mut ships = classic();
+        ships.insert("patrol_boat".to_owned(), vec![59, 60]);
+        assert_eq!(
+            reasons(fleet.validate(&ships)),
+            vec![ViolationReason::NotStraight]
+        );
+    }
+
+    #[test]
+    fn rejects_overlapping_ships() {
+        let mut ships = classic();
+        ships.insert("patrol_boat".to_owned(), vec![3, 13]);
+
+        assert_eq!(
+            FleetSpec::default().validate(&ships).unwrap_err(),
+            vec![PlacementViolation {
+                ship: Some("patrol_boat".to_owned()),
+                reason: ViolationReason::Overlaps {
+                    other: "carrier".to_owned()
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn counts_suffixed_ships_of_the_same_type() {
+        let fleet = FleetSpec::parse(10, 10, "destroyer:3x2").unwrap();
+
+        assert_eq!(
+            fleet.validate(&placement(&[
+                ("destroyer#1", &[0, 1, 2]),
+                ("destroyer#2", &[20, 21, 22])
+            ])),
+            Ok(())
+        );
+        assert_eq!(
+            reasons(fleet.validate(&placement(&[("destroyer", &[0, 1, 2])]))),
+            vec![ViolationReason::WrongCount {
+                r#type: "destroyer".to_owned(),
+                expected: 2,
+                actual: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_fleet_specs() {
+        assert!(FleetSpec::parse(10, 10, "carrier").is_err());
+        assert!(FleetSpec::parse(10, 10, "carrier:0").is_err());
+        assert!(FleetSpec::parse(10, 10, "").is_err());
+        assert!(FleetSpec::parse(20, 20, "carrier:5").is_err());
+        assert!(FleetSpec::parse(16, 16, "carrier:5").is_ok());
+    }
+}
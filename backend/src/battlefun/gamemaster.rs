@@ -1,32 +1,67 @@
-use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use tokio::sync::RwLock;
+use tokio::sync::oneshot;
 
-use super::kafka::StatefunKafkaClient;
-use super::{CellIndex, GameId, PlayerId, ShipPlacement};
+use super::clock::{Alarm, Clock, ClockConfig};
+use super::proto::from_game_fn::Response;
+use super::requests::PendingRequests;
+use super::shard::ShardedMap;
+use super::store::{seconds_since_epoch, GameRecord, GameStore};
+use super::transport::GameEngineTransport;
+use super::{CellIndex, GameId, PlayerId, RequestId, ShipPlacement};
 use crate::error::Error;
 
 pub struct GameMaster {
-    statefun_kafka_client: Arc<RwLock<StatefunKafkaClient>>,
-    pub games: HashMap<GameId, GameInfo>,
+    transport: Arc<dyn GameEngineTransport>,
+    requests: Arc<PendingRequests>,
+    store: Arc<GameStore>,
+    clock: ClockConfig,
+    pub games: ShardedMap<GameId, GameInfo>,
 }
 
 impl GameMaster {
-    pub fn new(statefun_kafka_client: Arc<RwLock<StatefunKafkaClient>>) -> Self {
+    pub fn new(
+        transport: Arc<dyn GameEngineTransport>,
+        requests: Arc<PendingRequests>,
+        store: Arc<GameStore>,
+        clock: ClockConfig,
+    ) -> Self {
         Self {
-            statefun_kafka_client,
-            games: HashMap::new(),
+            transport,
+            requests,
+            store,
+            clock,
+            games: ShardedMap::new(),
         }
     }
 
-    pub async fn start_game(
-        &mut self,
+    /// Adds every game saved in the game store, returning how many there were. Their state stays
+    /// in the game engine, so their players can carry on where they left off. Their clocks start
+    /// afresh once the engine next reports them.
+    pub fn restore(&self) -> Result<usize, Error> {
+        let records = self.store.load()?;
+
+        for record in &records {
+            let mut game_info = GameInfo::new(record.player1_id, record.player2_id);
+            game_info.clock = Clock::new(self.clock.clone());
+            game_info.started_at = UNIX_EPOCH + Duration::from_secs(record.started_at);
+
+            self.games.insert(record.game_id, game_info);
+        }
+
+        Ok(records.len())
+    }
+
+    /// Starts a game between the players. If the game engine cannot be asked to create it, the
+    /// game is forgotten again and the error returned.
+    pub fn start_game(
+        &self,
         player1_id: PlayerId,
         player1_ships: ShipPlacement,
         player2_id: PlayerId,
         player2_ships: ShipPlacement,
-    ) {
+    ) -> Result<GameId, Error> {
         let game_id = GameId::new_v4();
 
         eprintln!(
@@ -34,51 +69,200 @@ impl GameMaster {
             game_id, player1_id, player1_ships, player2_id, player2_ships
         );
 
-        self.games
-            .insert(game_id, GameInfo::new(player1_id, player2_id));
-
-        self.statefun_kafka_client
-            .write()
-            .await
-            .send_create_game(
-                game_id,
-                player1_id,
-                player1_ships,
-                player2_id,
-                player2_ships,
-            )
-            .await
-            .expect("uh-oh");
+        let mut game_info = GameInfo::new(player1_id, player2_id);
+        game_info.clock = Clock::new(self.clock.clone());
+        let record = GameRecord {
+            game_id,
+            player1_id,
+            player2_id,
+            started_at: seconds_since_epoch(game_info.started_at),
+        };
+        self.games.insert(game_id, game_info);
+
+        if let Err(e) = self.store.save(&record) {
+            eprintln!("Game {}: could not save game: {}", game_id, e);
+        }
+
+        if let Err(e) = self.transport.create_game(
+            RequestId::new_v4(),
+            game_id,
+            player1_id,
+            player1_ships,
+            player2_id,
+            player2_ships,
+        ) {
+            eprintln!("Game {}: could not create game: {}", game_id, e);
+            self.games.remove(&game_id);
+            if let Err(e) = self.store.remove(&game_id) {
+                eprintln!("Game {}: could not remove game: {}", game_id, e);
+            }
+            return Err(e);
+        }
+
+        Ok(game_id)
     }
 
-    pub async fn turn(
-        &mut self,
+    /// Sends the shot to the game engine, returning a receiver for the engine's evaluation of it.
+    pub fn turn(
+        &self,
         game_id: GameId,
         player_id: PlayerId,
         cell: CellIndex,
-    ) -> Result<(), Error> {
-        match self.games.get(&game_id) {
-            Some(game_info) => {
-                if player_id != game_info.player1_id && player_id != game_info.player2_id {
-                    return Err(Error::InvalidArgument("Invalid player".to_owned()));
-                }
-            }
-            None => return Err(Error::NoSuchGame(game_id)),
-        };
+    ) -> Result<oneshot::Receiver<Response>, Error> {
+        self.check_player(game_id, player_id)?;
 
         eprintln!(
             "Game {}: player {} took a shot @ {}",
             game_id, player_id, cell
         );
 
-        self.statefun_kafka_client
-            .write()
-            .await
-            .send_turn(game_id, player_id, cell)
-            .await
-            .expect("uh-oh");
+        let (request_id, reply) = self.requests.register(player_id);
+        self.transport.turn(request_id, game_id, player_id, cell)?;
+
+        Ok(reply)
+    }
+
+    pub fn resign(&self, game_id: GameId, player_id: PlayerId) -> Result<(), Error> {
+        self.check_player(game_id, player_id)?;
+
+        eprintln!("Game {}: player {} resigned", game_id, player_id);
+
+        let (request_id, _) = self.requests.register(player_id);
+        self.transport.resign(request_id, game_id, player_id)
+    }
+
+    /// Resigns the game for the player, who ran out of time, returning whether they had not been
+    /// resigned for already. If the engine cannot be told, the game is left as it was, to be
+    /// tried again.
+    pub fn time_out(&self, game_id: GameId, player_id: PlayerId) -> Result<bool, Error> {
+        let timed_out = self
+            .games
+            .with(&game_id, |game_info| game_info.timed_out.is_some());
+        if timed_out == Some(true) {
+            return Ok(false);
+        }
+
+        self.resign(game_id, player_id)?;
+        self.games
+            .update(&game_id, |game_info| game_info.timed_out = Some(player_id));
+
+        Ok(true)
+    }
+
+    /// Asks the engine for the state of the game, which is sent to its players and anyone
+    /// watching it.
+    pub fn request_status(&self, game_id: GameId) -> Result<(), Error> {
+        if !self.games.contains_key(&game_id) {
+            return Err(Error::NoSuchGame(game_id));
+        }
+
+        // Nobody waits on the reply, so it need not be registered.
+        self.transport.get_game_status(RequestId::new_v4(), game_id)
+    }
+
+    /// Drops the game without a result, returning it if there was one. The game engine is told to
+    /// end it too, by resigning it for player 1; the result it reports is for a game no longer
+    /// played here, so nothing comes of it.
+    pub fn abandon(&self, game_id: GameId) -> Option<GameInfo> {
+        let game_info = self.games.remove(&game_id)?;
+
+        if !game_info.finished {
+            // Nobody waits on the reply, so it need not be registered.
+            let player_id = game_info.player1_id;
+            if let Err(e) = self
+                .transport
+                .resign(RequestId::new_v4(), game_id, player_id)
+            {
+                eprintln!("Game {}: could not end abandoned game: {}", game_id, e);
+            }
+        }
+
+        if let Err(e) = self.store.remove(&game_id) {
+            eprintln!("Game {}: could not remove abandoned game: {}", game_id, e);
+        }
+
+        Some(game_info)
+    }
+
+    /// Marks the game finished, returning whether it had not been already. Lets the final result
+    /// of a game be acted on once, however many times the engine reports it.
+    pub fn finish(&self, game_id: GameId) -> bool {
+        let first = self
+            .games
+            .update(&game_id, |game_info| {
+                game_info.clock.stop(Instant::now());
+                !std::mem::replace(&mut game_info.finished, true)
+            })
+            .unwrap_or(false);
+
+        if first {
+            if let Err(e) = self.store.remove(&game_id) {
+                eprintln!("Game {}: could not remove finished game: {}", game_id, e);
+            }
+        }
+
+        first
+    }
+
+    /// Runs the clock of the player whose turn it is, or stops it if it is nobody's, returning the
+    /// clock as it then stands.
+    pub fn run_clock(&self, game_id: GameId, to_move: Option<PlayerId>) -> Option<Clock> {
+        self.games.update(&game_id, |game_info| {
+            let now = Instant::now();
+            match to_move {
+                Some(player_id) if !game_info.finished => game_info.clock.start(player_id, now),
+                _ => game_info.clock.stop(now),
+            }
+
+            game_info.clock.clone()
+        })
+    }
+
+    /// Checks the clock of every game in play, returning the alarms they sounded.
+    pub fn check_clocks(&self) -> Vec<(GameId, Alarm)> {
+        let mut timed = Vec::new();
+        self.games.for_each(|game_id, game_info| {
+            if !game_info.finished && game_info.clock.is_limited() {
+                timed.push(*game_id);
+            }
+        });
+
+        let now = Instant::now();
+        timed
+            .into_iter()
+            .filter_map(|game_id| {
+                let alarm = self
+                    .games
+                    .update(&game_id, |game_info| game_info.clock.check(now))??;
+                Some((game_id, alarm))
+            })
+            .collect()
+    }
+
+    fn check_player(&self, game_id: GameId, player_id: PlayerId) -> Result<(), Error> {
+        match self
+            .games
+            .with(&game_id, |game_info| game_info.has_player(player_id))
+        {
+            Some(true) => Ok(()),
+            Some(false) => Err(Error::Forbidden("not a player in this game".to_owned())),
+            None => Err(Error::NoSuchGame(game_id)),
+        }
+    }
+}
+
+#[cfg(test)]
+impl GameMaster {
+    /// A game master over the in-memory game engine and store, whose updates nobody reads.
+    pub fn in_memory() -> Self {
+        let (sender, _) = tokio::sync::mpsc::unbounded_channel();
 
-        Ok(())
+        Self::new(
+            Arc::new(super::memory::InMemoryGameEngine::new(sender)),
+            Arc::new(PendingRequests::new()),
+            Arc::new(super::store::InMemoryStore::new()),
+            Default::default(),
+        )
     }
 }
 
@@ -86,6 +270,11 @@ impl GameMaster {
 pub struct GameInfo {
     pub player1_id: PlayerId,
     pub player2_id: PlayerId,
+    pub finished: bool,
+    pub started_at: SystemTime,
+    pub clock: Clock,
+    /// The player who ran out of time, once they have been resigned for.
+    pub timed_out: Option<PlayerId>,
 }
 
 impl GameInfo {
@@ -93,6 +282,88 @@ impl GameInfo {
         Self {
             player1_id,
             player2_id,
+            finished: false,
+            started_at: SystemTime::now(),
+            clock: Clock::default(),
+            timed_out: None,
         }
     }
+
+    pub fn has_player(&self, player_id: PlayerId) -> bool {
+        player_id == self.player1_id || player_id == self.player2_id
+    }
+
+    /// The other player of the game, if `player_id` is one of its players.
+    pub fn opponent_of(&self, player_id: PlayerId) -> Option<PlayerId> {
+        if player_id == self.player1_id {
+            Some(self.player2_id)
+        } else if player_id == self.player2_id {
+            Some(self.player1_id)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::battlefun::store::{InMemoryStore, Store};
+    use crate::battlefun::transport::UnreachableEngine;
+
+    #[test]
+    fn forgets_games_the_engine_cannot_create() {
+        let store = Arc::new(InMemoryStore::new());
+        let gamemaster = GameMaster::new(
+            Arc::new(UnreachableEngine),
+            Arc::new(PendingRequests::new()),
+            store.clone(),
+            Default::default(),
+        );
+
+        let started = gamemaster.start_game(
+            PlayerId::new_v4(),
+            ShipPlacement::new(),
+            PlayerId::new_v4(),
+            ShipPlacement::new(),
+        );
+        assert!(started.is_err());
+        assert_eq!(gamemaster.games.len(), 0);
+        assert!(store.load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn times_players_out_once_the_engine_is_told() {
+        let gamemaster = GameMaster::new(
+            Arc::new(UnreachableEngine),
+            Arc::new(PendingRequests::new()),
+            Arc::new(InMemoryStore::new()),
+            Default::default(),
+        );
+        let (game_id, player_id) = (GameId::new_v4(), PlayerId::new_v4());
+        gamemaster
+            .games
+            .insert(game_id, GameInfo::new(player_id, PlayerId::new_v4()));
+
+        assert!(gamemaster.time_out(game_id, player_id).is_err());
+        assert_eq!(gamemaster.games.with(&game_id, |g| g.timed_out), Some(None));
+
+        let gamemaster = GameMaster::in_memory();
+        let game_id = gamemaster
+            .start_game(
+                player_id,
+                ShipPlacement::new(),
+                PlayerId::new_v4(),
+                ShipPlacement::new(),
+            )
+            .unwrap();
+
+        assert!(gamemaster.time_out(game_id, player_id).unwrap());
+        assert!(!gamemaster.time_out(game_id, player_id).unwrap());
+        assert_eq!(
+            gamemaster.games.with(&game_id, |g| g.timed_out),
+            Some(Some(player_id))
+        );
+    }
 }
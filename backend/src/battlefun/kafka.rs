@@ -1,21 +1,37 @@
+use std::sync::Arc;
+
+use futures::StreamExt;
 use prost::Message;
 use rdkafka::config::ClientConfig;
-use rdkafka::producer::{DeliveryFuture, FutureProducer, FutureRecord};
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::Message as KafkaMessage;
+use tokio::sync::mpsc;
 
 use super::proto::{
-    to_game_fn::{CreateGame, GetGameStatus, Msg, Turn},
-    ToGameFn,
+    from_game_fn::Response,
+    to_game_fn::{CreateGame, GetGameStatus, Msg, Resign, Turn},
+    FromGameFn, ToGameFn,
 };
-use super::{CellIndex, GameId, PlayerId, ShipPlacement, ToBattleFunProto};
+use super::transport::GameEngineTransport;
+use super::{BattleFun, CellIndex, GameId, PlayerId, RequestId, ShipPlacement, ToBattleFunProto};
 use crate::error::Error;
+use crate::game::FailureCode;
 
 pub struct StatefunKafkaClient {
     producer: FutureProducer,
     to_statefun_topic: String,
+    /// Where to report commands that could not be delivered, as failures of the requests that
+    /// sent them, so that whoever waits on one hears of it at once.
+    failures: mpsc::UnboundedSender<FromGameFn>,
 }
 
 impl StatefunKafkaClient {
-    pub fn new(brokers: &str, to_statefun_topic: String) -> Self {
+    pub fn new(
+        brokers: &str,
+        to_statefun_topic: String,
+        failures: mpsc::UnboundedSender<FromGameFn>,
+    ) -> Self {
         let producer: FutureProducer = ClientConfig::new()
             .set("bootstrap.servers", brokers)
             .create()
@@ -23,34 +39,21 @@ impl StatefunKafkaClient {
 
         Self {
             producer,
-            to_statefun_topic: to_statefun_topic.to_owned(),
+            to_statefun_topic,
+            failures,
         }
     }
 
-    pub async fn send_create_game(
-        &self,
-        game_id: GameId,
-        player1_id: PlayerId,
-        player1_ships: ShipPlacement,
-        player2_id: PlayerId,
-        player2_ships: ShipPlacement,
-    ) -> Result<DeliveryFuture, Error> {
-        let create_game_msg = CreateGame {
-            game_id: game_id.to_string(),
-            player1_id: player1_id.to_string(),
-            player2_id: player2_id.to_string(),
-            player1_placement: Some(player1_ships.to_proto()),
-            player2_placement: Some(player2_ships.to_proto()),
-        };
-
+    fn send(&self, request_id: RequestId, game_id: GameId, msg: Msg) -> Result<(), Error> {
         let message = ToGameFn {
             game_id: game_id.to_string(),
-            msg: Some(Msg::CreateGame(create_game_msg)),
+            request_id: request_id.to_string(),
+            msg: Some(msg),
         };
 
         let mut buf = vec![];
         if let Err(error) = message.encode(&mut buf) {
-            return Err(Error::ProtobufEncodeError(error.into()));
+            return Err(Error::ProtobufEncodeError(error));
         }
 
         let key = game_id.to_string();
@@ -59,70 +62,139 @@ impl StatefunKafkaClient {
                 .payload(&buf)
                 .key(&key),
         );
-        match delivery_state {
-            Ok(f) => Ok(f),
-            Err((error, _)) => Err(Error::KafkaError(error.into())),
-        }
+        let delivery = match delivery_state {
+            Ok(f) => f,
+            Err((error, _)) => return Err(Error::KafkaError(error)),
+        };
+
+        let failures = self.failures.clone();
+        tokio::spawn(async move {
+            let error = match delivery.await {
+                Ok(Ok(_)) => return,
+                Ok(Err((error, _))) => error.to_string(),
+                Err(_) => "delivery canceled".to_owned(),
+            };
+
+            eprintln!(
+                "Game {}: could not deliver request {} to statefun: {}",
+                game_id, request_id, error
+            );
+            let failure = FailureCode::NotDelivered.failure("Could not reach the game engine");
+            let _ = failures.send(FromGameFn {
+                game_id: game_id.to_string(),
+                request_id: request_id.to_string(),
+                response: Some(Response::Failure(failure)),
+            });
+        });
+
+        Ok(())
+    }
+}
+
+impl GameEngineTransport for StatefunKafkaClient {
+    fn create_game(
+        &self,
+        request_id: RequestId,
+        game_id: GameId,
+        player1_id: PlayerId,
+        player1_ships: ShipPlacement,
+        player2_id: PlayerId,
+        player2_ships: ShipPlacement,
+    ) -> Result<(), Error> {
+        let create_game_msg = CreateGame {
+            game_id: game_id.to_string(),
+            player1_id: player1_id.to_string(),
+            player2_id: player2_id.to_string(),
+            player1_placement: Some(player1_ships.to_proto()),
+            player2_placement: Some(player2_ships.to_proto()),
+        };
+
+        self.send(request_id, game_id, Msg::CreateGame(create_game_msg))
     }
 
-    pub async fn send_turn(
+    fn turn(
         &self,
+        request_id: RequestId,
         game_id: GameId,
         player_id: PlayerId,
         cell: CellIndex,
-    ) -> Result<DeliveryFuture, Error> {
+    ) -> Result<(), Error> {
         let turn_msg = Turn {
             game_id: game_id.to_string(),
             player_id: player_id.to_string(),
             shot: cell as i64,
         };
 
-        let message = ToGameFn {
-            game_id: game_id.to_string(),
-            msg: Some(Msg::Turn(turn_msg)),
-        };
-
-        let mut buf = vec![];
-        if let Err(error) = message.encode(&mut buf) {
-            return Err(Error::ProtobufEncodeError(error.into()));
-        }
-
-        let key = game_id.to_string();
-        let delivery_state = self.producer.send_result(
-            FutureRecord::to(&self.to_statefun_topic)
-                .payload(&buf)
-                .key(&key),
-        );
-        match delivery_state {
-            Ok(f) => Ok(f),
-            Err((error, _)) => Err(Error::KafkaError(error.into())),
-        }
+        self.send(request_id, game_id, Msg::Turn(turn_msg))
     }
 
-    pub async fn send_get_game_status(&self, game_id: &GameId) -> Result<DeliveryFuture, Error> {
+    fn get_game_status(&self, request_id: RequestId, game_id: GameId) -> Result<(), Error> {
         let get_game_status_msg = GetGameStatus {
             game_id: game_id.to_string(),
         };
 
-        let message = ToGameFn {
+        self.send(request_id, game_id, Msg::GetGameStatus(get_game_status_msg))
+    }
+
+    fn resign(
+        &self,
+        request_id: RequestId,
+        game_id: GameId,
+        player_id: PlayerId,
+    ) -> Result<(), Error> {
+        let resign_msg = Resign {
             game_id: game_id.to_string(),
-            msg: Some(Msg::GetGameStatus(get_game_status_msg)),
+            player_id: player_id.to_string(),
         };
 
-        let mut buf = vec![];
-        if let Err(error) = message.encode(&mut buf) {
-            return Err(Error::ProtobufEncodeError(error.into()));
-        }
+        self.send(request_id, game_id, Msg::Resign(resign_msg))
+    }
+}
 
-        let key = game_id.to_string();
-        let delivery_state = self.producer.send_result(
-            FutureRecord::to(&self.to_statefun_topic)
-                .payload(&buf)
-                .key(&key),
-        );
-        match delivery_state {
-            Ok(f) => Ok(f),
-            Err((error, _)) => Err(Error::KafkaError(error.into())),
+pub struct StatefunKafkaConsumer {
+    consumer: StreamConsumer,
+}
+
+impl StatefunKafkaConsumer {
+    pub fn new(brokers: &str, group_id: &str, from_statefun_topic: &str) -> Self {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("group.id", group_id)
+            .set("enable.auto.commit", "false")
+            .set("enable.partition.eof", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .expect("Consumer creation error");
+
+        consumer
+            .subscribe(&[from_statefun_topic])
+            .expect("Consumer subscription error");
+
+        Self { consumer }
+    }
+
+    /// Feeds every record on the from-statefun topic into `battlefun`, committing its offset once
+    /// it has been handled. Undecodable records are logged and skipped rather than retried.
+    pub async fn run(&self, battlefun: Arc<BattleFun>) {
+        let mut stream = self.consumer.start();
+
+        while let Some(result) = stream.next().await {
+            let record = match result {
+                Ok(r) => r,
+                Err(e) => {
+                    eprintln!("error receiving from statefun: {}", e);
+                    continue;
+                }
+            };
+
+            match FromGameFn::decode(record.payload().unwrap_or_default()) {
+                Ok(message) => battlefun.handle_statefun_message(message),
+                Err(e) => eprintln!("error decoding message from statefun: {}", e),
+            }
+
+            if let Err(e) = self.consumer.commit_message(&record, CommitMode::Async) {
+                eprintln!("error committing offset: {}", e);
+            }
         }
     }
 }
@@ -0,0 +1,346 @@
+//! Private games, started either through a lobby whose code the host shares with their opponent,
+//! or by challenging a specific player.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use super::{
+    gamemaster::{GameInfo, GameMaster},
+    GameId, PlayerId, ShipPlacement,
+};
+use crate::error::Error;
+
+/// A short code naming a lobby, easy to read out or type in.
+pub type LobbyCode = String;
+pub type ChallengeId = Uuid;
+
+const CODE_LENGTH: usize = 6;
+/// Letters and digits, leaving out those easily mistaken for one another.
+const CODE_ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+pub struct Lobbies {
+    gamemaster: Arc<GameMaster>,
+    lobbies: Mutex<HashMap<LobbyCode, Invitation>>,
+    challenges: Mutex<HashMap<ChallengeId, Challenge>>,
+    /// How long a lobby or challenge stays open without being joined or answered.
+    ttl: Duration,
+}
+
+#[derive(Debug, Clone)]
+struct Invitation {
+    host_id: PlayerId,
+    ships: ShipPlacement,
+    created_at: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    pub challenger_id: PlayerId,
+    pub challenged_id: PlayerId,
+    ships: ShipPlacement,
+    created_at: Instant,
+}
+
+impl Lobbies {
+    pub fn new(gamemaster: Arc<GameMaster>, ttl: Duration) -> Self {
+        Self {
+            gamemaster,
+            lobbies: Mutex::new(HashMap::new()),
+            challenges: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Opens a lobby hosted by `host_id`, closing any other lobby they host.
+    pub fn create(&self, host_id: PlayerId, ships: ShipPlacement) -> LobbyCode {
+        let mut lobbies = self.lobbies.lock().unwrap();
+        lobbies.retain(|_, lobby| lobby.host_id != host_id);
+
+        let code = loop {
+            let code = new_code();
+            if !lobbies.contains_key(&code) {
+                break code;
+            }
+        };
+
+        lobbies.insert(
+            code.clone(),
+            Invitation {
+                host_id,
+                ships,
+                created_at: Instant::now(),
+            },
+        );
+
+        code
+    }
+
+    /// Starts a game between the host of the lobby and `player_id`, closing the lobby. The lobby
+    /// stays open if the game cannot be started.
+    pub fn join(
+        &self,
+        code: &str,
+        player_id: PlayerId,
+        ships: ShipPlacement,
+    ) -> Result<(GameId, GameInfo), Error> {
+        let code = code.to_uppercase();
+        let lobby = {
+            let mut lobbies = self.lobbies.lock().unwrap();
+            match lobbies.get(&code) {
+                Some(lobby) if lobby.created_at.elapsed() < self.ttl => {
+                    if lobby.host_id == player_id {
+                        return Err(Error::InvalidArgument(
+                            "cannot join your own lobby".to_owned(),
+                        ));
+                    }
+                    lobbies.remove(&code).unwrap()
+                }
+                _ => return Err(Error::NoSuchLobby(code)),
+            }
+        };
+
+        self.start_game(lobby.host_id, lobby.ships.clone(), player_id, ships)
+            .map_err(|e| {
+                self.lobbies.lock().unwrap().insert(code, lobby);
+                e
+            })
+    }
+
+    /// Closes a lobby hosted by `host_id`.
+    pub fn close(&self, code: &str, host_id: PlayerId) -> Result<(), Error> {
+        let code = code.to_uppercase();
+        let mut lobbies = self.lobbies.lock().unwrap();
+
+        match lobbies.get(&code) {
+            Some(lobby) if lobby.host_id == host_id => {
+                lobbies.remove(&code);
+                Ok(())
+            }
+            Some(_) => Err(Error::Forbidden(
+                "only the host can close a lobby".to_owned(),
+            )),
+            None => Err(Error::NoSuchLobby(code)),
+        }
+    }
+
+    /// Records a challenge from `challenger_id` to `challenged_id`, which stays open until it is
+    /// answered or expires.
+    pub fn challenge(
+        &self,
+        challenger_id: PlayerId,
+        challenged_id: PlayerId,
+        ships: ShipPlacement,
+    ) -> ChallengeId {
+        let challenge_id = ChallengeId::new_v4();
+
+        self.challenges.lock().unwrap().insert(
+            challenge_id,
+            Challenge {
+                challenger_id,
+                challenged_id,
+                ships,
+                created_at: Instant::now(),
+            },
+        );
+
+        challenge_id
+    }
+
+    /// Starts the game `player_id` was challenged to. The challenge stays open if the game cannot
+    /// be started.
+    pub fn accept(
+        &self,
+        challenge_id: ChallengeId,
+        player_id: PlayerId,
+        ships: ShipPlacement,
+    ) -> Result<(GameId, GameInfo), Error> {
+        let challenge = self.answer(challenge_id, player_id)?;
+
+        self.start_game(
+            challenge.challenger_id,
+            challenge.ships.clone(),
+            player_id,
+            ships,
+        )
+        .map_err(|e| {
+            self.challenges
+                .lock()
+                .unwrap()
+                .insert(challenge_id, challenge);
+            e
+        })
+    }
+
+    /// Turns down the challenge `player_id` received, returning it.
+    pub fn decline(
+        &self,
+        challenge_id: ChallengeId,
+        player_id: PlayerId,
+    ) -> Result<Challenge, Error> {
+        self.answer(challenge_id, player_id)
+    }
+
+    /// Closes the lobby `player_id` hosts and drops every challenge they sent or received, for a
+    /// player who is leaving.
+    pub fn forget(&self, player_id: PlayerId) {
+        self.lobbies
+            .lock()
+            .unwrap()
+            .retain(|_, lobby| lobby.host_id != player_id);
+        self.challenges.lock().unwrap().retain(|_, challenge| {
+            challenge.challenger_id != player_id && challenge.challenged_id != player_id
+        });
+    }
+
+    /// Closes lobbies and challenges that have been open for longer than the TTL.
+    pub fn expire(&self) {
+        let ttl = self.ttl;
+
+        self.lobbies
+            .lock()
+            .unwrap()
+            .retain(|_, lobby| lobby.created_at.elapsed() < ttl);
+        self.challenges
+            .lock()
+            .unwrap()
+            .retain(|_, challenge| challenge.created_at.elapsed() < ttl);
+    }
+
+    /// Removes the open challenge `challenge_id` if it was sent to `player_id`.
+    fn answer(&self, challenge_id: ChallengeId, player_id: PlayerId) -> Result<Challenge, Error> {
+        let mut challenges = self.challenges.lock().unwrap();
+
+        match challenges.get(&challenge_id) {
+            Some(challenge)
+                if challenge.challenged_id == player_id
+                    && challenge.created_at.elapsed() < self.ttl =>
+            {
+                Ok(challenges.remove(&challenge_id).unwrap())
+            }
+            _ => Err(Error::NoSuchChallenge(challenge_id)),
+        }
+    }
+
+    fn start_game(
+        &self,
+        player1_id: PlayerId,
+        player1_ships: ShipPlacement,
+        player2_id: PlayerId,
+        player2_ships: ShipPlacement,
+    ) -> Result<(GameId, GameInfo), Error> {
+        let game_id =
+            self.gamemaster
+                .start_game(player1_id, player1_ships, player2_id, player2_ships)?;
+
+        Ok((game_id, GameInfo::new(player1_id, player2_id)))
+    }
+}
+
+fn new_code() -> LobbyCode {
+    // Each random byte picks a letter; the alphabet's 32 letters divide 256 evenly.
+    Uuid::new_v4().as_bytes()[..CODE_LENGTH]
+        .iter()
+        .map(|&b| CODE_ALPHABET[b as usize % CODE_ALPHABET.len()] as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lobbies(ttl: Duration) -> Lobbies {
+        Lobbies::new(Arc::new(GameMaster::in_memory()), ttl)
+    }
+
+    #[test]
+    fn starts_games_from_lobby_codes() {
+        let lobbies = lobbies(Duration::from_secs(60));
+        let (host, guest) = (PlayerId::new_v4(), PlayerId::new_v4());
+
+        let code = lobbies.create(host, ShipPlacement::new());
+        assert_eq!(code.len(), CODE_LENGTH);
+        assert!(lobbies.join(&code, host, ShipPlacement::new()).is_err());
+
+        let (game_id, game_info) = lobbies
+            .join(&code.to_lowercase(), guest, ShipPlacement::new())
+            .unwrap();
+        assert_eq!((game_info.player1_id, game_info.player2_id), (host, guest));
+        assert!(lobbies.gamemaster.games.contains_key(&game_id));
+
+        assert!(matches!(
+            lobbies.join(&code, guest, ShipPlacement::new()),
+            Err(Error::NoSuchLobby(_))
+        ));
+    }
+
+    #[test]
+    fn only_the_host_closes_a_lobby() {
+        let lobbies = lobbies(Duration::from_secs(60));
+        let host = PlayerId::new_v4();
+
+        let code = lobbies.create(host, ShipPlacement::new());
+        assert!(lobbies.close(&code, PlayerId::new_v4()).is_err());
+        assert!(lobbies.close(&code, host).is_ok());
+        assert!(lobbies.close(&code, host).is_err());
+    }
+
+    #[test]
+    fn only_the_challenged_player_answers_a_challenge() {
+        let lobbies = lobbies(Duration::from_secs(60));
+        let (challenger, challenged) = (PlayerId::new_v4(), PlayerId::new_v4());
+
+        let challenge_id = lobbies.challenge(challenger, challenged, ShipPlacement::new());
+        assert!(lobbies
+            .accept(challenge_id, challenger, ShipPlacement::new())
+            .is_err());
+
+        let declined = lobbies.decline(challenge_id, challenged).unwrap();
+        assert_eq!(declined.challenger_id, challenger);
+        assert!(lobbies
+            .accept(challenge_id, challenged, ShipPlacement::new())
+            .is_err());
+
+        let challenge_id = lobbies.challenge(challenger, challenged, ShipPlacement::new());
+        let (_, game_info) = lobbies
+            .accept(challenge_id, challenged, ShipPlacement::new())
+            .unwrap();
+        assert_eq!(game_info.player1_id, challenger);
+    }
+
+    #[test]
+    fn forgets_players_who_leave() {
+        let lobbies = lobbies(Duration::from_secs(60));
+        let (leaver, other) = (PlayerId::new_v4(), PlayerId::new_v4());
+
+        lobbies.create(leaver, ShipPlacement::new());
+        let kept = lobbies.create(other, ShipPlacement::new());
+        lobbies.challenge(leaver, other, ShipPlacement::new());
+        lobbies.challenge(other, leaver, ShipPlacement::new());
+
+        lobbies.forget(leaver);
+        assert_eq!(
+            lobbies.lobbies.lock().unwrap().keys().collect::<Vec<_>>(),
+            vec![&kept]
+        );
+        assert!(lobbies.challenges.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn expires_unanswered_invitations() {
+        let lobbies = lobbies(Duration::from_secs(0));
+        let (host, guest) = (PlayerId::new_v4(), PlayerId::new_v4());
+
+        let code = lobbies.create(host, ShipPlacement::new());
+        let challenge_id = lobbies.challenge(host, guest, ShipPlacement::new());
+
+        assert!(lobbies.join(&code, guest, ShipPlacement::new()).is_err());
+        assert!(lobbies.decline(challenge_id, guest).is_err());
+
+        lobbies.expire();
+        assert!(lobbies.lobbies.lock().unwrap().is_empty());
+        assert!(lobbies.challenges.lock().unwrap().is_empty());
+    }
+}
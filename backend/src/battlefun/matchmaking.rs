@@ -1,44 +1,374 @@
-use super::{gamemaster::GameMaster, PlayerId, ShipPlacement};
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use super::{
+    bot::Difficulty,
+    gamemaster::{GameInfo, GameMaster},
+    GameId, PlayerId, ShipPlacement,
+};
+use crate::error::Error;
+
+/// How far apart two players' ratings may be for them to be matched. The window starts narrow and
+/// widens the longer a player waits, so that nobody waits forever for an evenly matched opponent.
+#[derive(Debug, Clone)]
+pub struct RatingWindow {
+    pub initial: f64,
+    pub growth_per_sec: f64,
+}
+
+impl RatingWindow {
+    fn after(&self, waited: Duration) -> f64 {
+        self.initial + self.growth_per_sec * waited.as_secs_f64()
+    }
+}
+
+/// Pairs queued players with the longest-waiting player close enough to them in rating.
 pub struct Matchmaker {
-    gamemaster: Arc<RwLock<GameMaster>>,
-    waiting_player: Option<WaitingPlayer>,
+    gamemaster: Arc<GameMaster>,
+    queue: Mutex<VecDeque<WaitingPlayer>>,
+    timeout: Duration,
+    window: RatingWindow,
+}
+
+#[derive(Debug, Clone)]
+pub struct WaitingPlayer {
+    pub id: PlayerId,
+    pub ships: ShipPlacement,
+    /// The bot to play instead if nobody is matched with the player before the queue timeout.
+    pub bot_fallback: Option<Difficulty>,
+    rating: f64,
+    queued_at: Instant,
+    /// Whether the player had a WebSocket open when they queued. Such players are dropped from
+    /// the queue once it closes; players queueing over REST alone are kept.
+    connected: bool,
 }
 
 impl Matchmaker {
-    pub fn new(gamemaster: Arc<RwLock<GameMaster>>) -> Self {
+    pub fn new(gamemaster: Arc<GameMaster>, timeout: Duration, window: RatingWindow) -> Self {
         Self {
             gamemaster,
-            waiting_player: None,
+            queue: Mutex::new(VecDeque::new()),
+            timeout,
+            window,
         }
     }
 
-    pub async fn play(&mut self, new_player_id: PlayerId, new_player_ships: ShipPlacement) {
-        if let Some(waiting_player) = self.waiting_player.take() {
-            self.gamemaster
-                .write()
-                .await
-                .start_game(
-                    waiting_player.id,
-                    waiting_player.ships,
-                    new_player_id,
-                    new_player_ships,
-                )
-                .await;
-            self.waiting_player = None;
-        } else {
-            self.waiting_player = Some(WaitingPlayer {
-                id: new_player_id,
-                ships: new_player_ships,
+    /// Queues the player for a game, returning the game if a suitable opponent was already
+    /// waiting. A player who is already queued keeps their place, with their new ships. If the
+    /// game cannot be started, the opponent goes back to the head of the queue.
+    ///
+    /// `is_connected` tells whether a player has a WebSocket open, or `None` if they are no longer
+    /// registered; waiting players who have gone away are dropped rather than matched.
+    pub fn play(
+        &self,
+        new_player_id: PlayerId,
+        new_player_ships: ShipPlacement,
+        rating: f64,
+        bot_fallback: Option<Difficulty>,
+        is_connected: impl Fn(&PlayerId) -> Option<bool>,
+    ) -> Result<Option<(GameId, GameInfo)>, Error> {
+        let now = Instant::now();
+
+        let waiting_player = {
+            let mut queue = self.queue.lock().unwrap();
+
+            if let Some(queued) = queue.iter_mut().find(|w| w.id == new_player_id) {
+                queued.ships = new_player_ships;
+                queued.bot_fallback = bot_fallback;
+                return Ok(None);
+            }
+
+            queue.retain(|w| match is_connected(&w.id) {
+                Some(connected) => connected || !w.connected,
+                None => false,
             });
+
+            let opponent = queue
+                .iter()
+                .position(|w| {
+                    let waited = now.duration_since(w.queued_at);
+                    waited < self.timeout && (w.rating - rating).abs() <= self.window.after(waited)
+                })
+                .and_then(|position| queue.remove(position));
+
+            match opponent {
+                Some(waiting_player) => waiting_player,
+                None => {
+                    queue.push_back(WaitingPlayer {
+                        id: new_player_id,
+                        ships: new_player_ships,
+                        bot_fallback,
+                        rating,
+                        queued_at: now,
+                        connected: is_connected(&new_player_id) == Some(true),
+                    });
+
+                    return Ok(None);
+                }
+            }
+        };
+
+        match self.gamemaster.start_game(
+            waiting_player.id,
+            waiting_player.ships.clone(),
+            new_player_id,
+            new_player_ships,
+        ) {
+            Ok(game_id) => Ok(Some((
+                game_id,
+                GameInfo::new(waiting_player.id, new_player_id),
+            ))),
+            Err(e) => {
+                self.queue.lock().unwrap().push_front(waiting_player);
+                Err(e)
+            }
         }
     }
+
+    /// Matches waiting players whose rating windows have widened enough to take each other in,
+    /// returning the games started for them. Players whose game cannot be started go back to the
+    /// head of the queue, to be matched again next time.
+    pub fn pair_waiting(&self) -> Vec<(GameId, GameInfo)> {
+        let now = Instant::now();
+        let mut pairs = Vec::new();
+
+        {
+            let mut queue = self.queue.lock().unwrap();
+            let mut i = 0;
+
+            while i < queue.len() {
+                let window = |w: &WaitingPlayer| self.window.after(now.duration_since(w.queued_at));
+                let first = &queue[i];
+                let opponent = (i + 1..queue.len()).find(|&j| {
+                    let second = &queue[j];
+                    (first.rating - second.rating).abs() <= window(first).max(window(second))
+                });
+
+                match opponent {
+                    Some(j) => {
+                        let second = queue.remove(j).unwrap();
+                        let first = queue.remove(i).unwrap();
+                        pairs.push((first, second));
+                    }
+                    None => i += 1,
+                }
+            }
+        }
+
+        let mut started = Vec::new();
+        for (first, second) in pairs {
+            match self.gamemaster.start_game(
+                first.id,
+                first.ships.clone(),
+                second.id,
+                second.ships.clone(),
+            ) {
+                Ok(game_id) => started.push((game_id, GameInfo::new(first.id, second.id))),
+                Err(e) => {
+                    eprintln!(
+                        "could not start game for {} and {}: {}",
+                        first.id, second.id, e
+                    );
+                    let mut queue = self.queue.lock().unwrap();
+                    queue.push_front(second);
+                    queue.push_front(first);
+                }
+            }
+        }
+
+        started
+    }
+
+    /// Takes the player out of the queue, returning whether they were in it.
+    pub fn cancel(&self, player_id: &PlayerId) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        let before = queue.len();
+        queue.retain(|w| w.id != *player_id);
+
+        queue.len() != before
+    }
+
+    /// Takes every player who has waited longer than the timeout out of the queue, returning them.
+    pub fn expire(&self) -> Vec<WaitingPlayer> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+
+        self.queue.lock().unwrap().retain(|w| {
+            let waiting = now.duration_since(w.queued_at) < self.timeout;
+            if !waiting {
+                expired.push(w.clone());
+            }
+            waiting
+        });
+
+        expired
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
 }
 
-#[derive(Debug, Clone)]
-struct WaitingPlayer {
-    id: PlayerId,
-    ships: ShipPlacement,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battlefun::rating::INITIAL_RATING;
+    use crate::battlefun::requests::PendingRequests;
+    use crate::battlefun::store::InMemoryStore;
+    use crate::battlefun::transport::UnreachableEngine;
+
+    fn window() -> RatingWindow {
+        RatingWindow {
+            initial: 100.0,
+            growth_per_sec: 0.0,
+        }
+    }
+
+    fn matchmaker(timeout: Duration) -> Matchmaker {
+        Matchmaker::new(Arc::new(GameMaster::in_memory()), timeout, window())
+    }
+
+    fn anyone(_: &PlayerId) -> Option<bool> {
+        Some(false)
+    }
+
+    /// Queues the player at the initial rating, without ships or a bot to fall back on.
+    fn queue(matchmaker: &Matchmaker, player_id: PlayerId) -> Option<(GameId, GameInfo)> {
+        matchmaker
+            .play(
+                player_id,
+                ShipPlacement::new(),
+                INITIAL_RATING,
+                None,
+                anyone,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn pairs_players_in_arrival_order() {
+        let matchmaker = matchmaker(Duration::from_secs(60));
+        let players: Vec<PlayerId> = (0..3).map(|_| PlayerId::new_v4()).collect();
+
+        assert!(queue(&matchmaker, players[0]).is_none());
+        assert!(queue(&matchmaker, players[1]).is_some());
+        assert!(queue(&matchmaker, players[2]).is_none());
+
+        let (game_id, game_info) = queue(&matchmaker, PlayerId::new_v4()).unwrap();
+        assert_eq!(game_info.player1_id, players[2]);
+        assert!(matchmaker.gamemaster.games.contains_key(&game_id));
+        assert_eq!(matchmaker.len(), 0);
+    }
+
+    #[test]
+    fn never_matches_players_against_themselves() {
+        let matchmaker = matchmaker(Duration::from_secs(60));
+        let player_id = PlayerId::new_v4();
+
+        assert!(queue(&matchmaker, player_id).is_none());
+        assert!(queue(&matchmaker, player_id).is_none());
+        assert_eq!(matchmaker.len(), 1);
+    }
+
+    #[test]
+    fn cancels_and_expires_waiting_players() {
+        let matchmaker = matchmaker(Duration::from_secs(60));
+        let player_id = PlayerId::new_v4();
+
+        queue(&matchmaker, player_id);
+        assert!(matchmaker.cancel(&player_id));
+        assert!(!matchmaker.cancel(&player_id));
+        assert!(matchmaker.expire().is_empty());
+
+        let matchmaker = self::matchmaker(Duration::from_secs(0));
+        queue(&matchmaker, player_id);
+        assert!(queue(&matchmaker, PlayerId::new_v4()).is_none());
+        assert_eq!(matchmaker.expire().len(), 2);
+        assert_eq!(matchmaker.len(), 0);
+    }
+
+    #[test]
+    fn skips_players_who_went_away() {
+        let matchmaker = matchmaker(Duration::from_secs(60));
+        let players: Vec<PlayerId> = (0..4).map(|_| PlayerId::new_v4()).collect();
+
+        // Connected when queueing, and disconnected since.
+        matchmaker
+            .play(
+                players[0],
+                ShipPlacement::new(),
+                INITIAL_RATING,
+                None,
+                |_| Some(true),
+            )
+            .unwrap();
+        assert!(queue(&matchmaker, players[1]).is_none());
+
+        // Deregistered since.
+        let deregistered = |id: &PlayerId| Some(false).filter(|_| *id != players[1]);
+        assert!(matchmaker
+            .play(
+                players[2],
+                ShipPlacement::new(),
+                INITIAL_RATING,
+                None,
+                deregistered
+            )
+            .unwrap()
+            .is_none());
+
+        let (_, game_info) = queue(&matchmaker, players[3]).unwrap();
+        assert_eq!(game_info.player1_id, players[2]);
+    }
+
+    #[test]
+    fn pairs_players_within_the_rating_window() {
+        let mut matchmaker = matchmaker(Duration::from_secs(60));
+        let players: Vec<PlayerId> = (0..4).map(|_| PlayerId::new_v4()).collect();
+
+        matchmaker
+            .play(players[0], ShipPlacement::new(), 1500.0, None, anyone)
+            .unwrap();
+        assert!(matchmaker
+            .play(players[1], ShipPlacement::new(), 1900.0, None, anyone)
+            .unwrap()
+            .is_none());
+
+        let (_, game_info) = matchmaker
+            .play(players[2], ShipPlacement::new(), 1850.0, None, anyone)
+            .unwrap()
+            .unwrap();
+        assert_eq!(game_info.player1_id, players[1]);
+        assert!(matchmaker.pair_waiting().is_empty());
+
+        matchmaker
+            .play(players[3], ShipPlacement::new(), 1000.0, None, anyone)
+            .unwrap();
+        matchmaker.window.initial = 500.0;
+        let games = matchmaker.pair_waiting();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].1.player1_id, players[0]);
+        assert_eq!(games[0].1.player2_id, players[3]);
+        assert_eq!(matchmaker.len(), 0);
+    }
+
+    #[test]
+    fn requeues_players_whose_game_cannot_start() {
+        let gamemaster = GameMaster::new(
+            Arc::new(UnreachableEngine),
+            Arc::new(PendingRequests::new()),
+            Arc::new(InMemoryStore::new()),
+            Default::default(),
+        );
+        let matchmaker = Matchmaker::new(Arc::new(gamemaster), Duration::from_secs(60), window());
+        let (waiting, joining) = (PlayerId::new_v4(), PlayerId::new_v4());
+
+        assert!(queue(&matchmaker, waiting).is_none());
+        assert!(matchmaker
+            .play(joining, ShipPlacement::new(), INITIAL_RATING, None, anyone)
+            .is_err());
+        assert_eq!(matchmaker.len(), 1);
+        assert!(matchmaker.cancel(&waiting));
+    }
 }
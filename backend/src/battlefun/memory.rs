@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc;
+
+use super::proto::{
+    from_game_fn::{Failure, GameUpdate, Response},
+    to_game_fn::{CreateGame, Resign, Turn},
+    FromGameFn,
+};
+use super::transport::GameEngineTransport;
+use super::{BattleFun, CellIndex, GameId, PlayerId, RequestId, ShipPlacement, ToBattleFunProto};
+use crate::error::Error;
+use crate::game::{self, FailureCode};
+
+/// A game engine that keeps every game in process memory, standing in for statefun when running
+/// without Kafka and Flink.
+pub struct InMemoryGameEngine {
+    games: Mutex<HashMap<GameId, GameUpdate>>,
+    updates: mpsc::UnboundedSender<FromGameFn>,
+}
+
+impl InMemoryGameEngine {
+    pub fn new(updates: mpsc::UnboundedSender<FromGameFn>) -> Self {
+        Self {
+            games: Mutex::new(HashMap::new()),
+            updates,
+        }
+    }
+
+    /// Moves the game on with `next`, forgetting it once it is over since no further request can
+    /// change it.
+    fn advance<F>(&self, game_id: GameId, next: F) -> Response
+    where
+        F: FnOnce(&GameUpdate) -> Result<GameUpdate, Failure>,
+    {
+        let mut games = self.games.lock().unwrap();
+
+        let game = match games.get_mut(&game_id) {
+            Some(game) => game,
+            None => return Response::Failure(unknown_game()),
+        };
+
+        match next(game) {
+            Ok(next) => {
+                if game::is_game_over(&next) {
+                    games.remove(&game_id);
+                } else {
+                    *game = next.clone();
+                }
+                Response::GameUpdate(next)
+            }
+            Err(failure) => Response::Failure(failure),
+        }
+    }
+
+    fn respond(&self, request_id: RequestId, game_id: GameId, response: Response) {
+        let _ = self.updates.send(FromGameFn {
+            game_id: game_id.to_string(),
+            request_id: request_id.to_string(),
+            response: Some(response),
+        });
+    }
+}
+
+impl GameEngineTransport for InMemoryGameEngine {
+    fn create_game(
+        &self,
+        request_id: RequestId,
+        game_id: GameId,
+        player1_id: PlayerId,
+        player1_ships: ShipPlacement,
+        player2_id: PlayerId,
+        player2_ships: ShipPlacement,
+    ) -> Result<(), Error> {
+        let game = game::create(&CreateGame {
+            game_id: game_id.to_string(),
+            player1_id: player1_id.to_string(),
+            player2_id: player2_id.to_string(),
+            player1_placement: Some(player1_ships.to_proto()),
+            player2_placement: Some(player2_ships.to_proto()),
+        });
+
+        self.games.lock().unwrap().insert(game_id, game.clone());
+        self.respond(request_id, game_id, Response::GameUpdate(game));
+
+        Ok(())
+    }
+
+    fn turn(
+        &self,
+        request_id: RequestId,
+        game_id: GameId,
+        player_id: PlayerId,
+        cell: CellIndex,
+    ) -> Result<(), Error> {
+        let turn = Turn {
+            game_id: game_id.to_string(),
+            player_id: player_id.to_string(),
+            shot: cell as i64,
+        };
+
+        let response = self.advance(game_id, |game| game::apply(game, &turn));
+        self.respond(request_id, game_id, response);
+
+        Ok(())
+    }
+
+    fn get_game_status(&self, request_id: RequestId, game_id: GameId) -> Result<(), Error> {
+        let response = match self.games.lock().unwrap().get(&game_id) {
+            Some(game) => Response::GameUpdate(game.clone()),
+            None => Response::Failure(unknown_game()),
+        };
+
+        self.respond(request_id, game_id, response);
+
+        Ok(())
+    }
+
+    fn resign(
+        &self,
+        request_id: RequestId,
+        game_id: GameId,
+        player_id: PlayerId,
+    ) -> Result<(), Error> {
+        let resign = Resign {
+            game_id: game_id.to_string(),
+            player_id: player_id.to_string(),
+        };
+
+        let response = self.advance(game_id, |game| game::resign(game, &resign));
+        self.respond(request_id, game_id, response);
+
+        Ok(())
+    }
+}
+
+/// Delivers the engine's results to `battlefun` the same way the Kafka consumer does.
+pub async fn forward_updates(
+    mut updates: mpsc::UnboundedReceiver<FromGameFn>,
+    battlefun: Arc<BattleFun>,
+) {
+    while let Some(message) = updates.recv().await {
+        battlefun.handle_statefun_message(message);
+    }
+}
+
+fn unknown_game() -> Failure {
+    FailureCode::UnknownGame.failure("Unknown game")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battlefun::proto::GameStatus;
+
+    fn ships(cells: &[CellIndex]) -> ShipPlacement {
+        let mut ships = ShipPlacement::new();
+        ships.insert("patrol_boat".to_owned(), cells.to_vec());
+        ships
+    }
+
+    fn next_response(updates: &mut mpsc::UnboundedReceiver<FromGameFn>) -> Response {
+        updates.try_recv().unwrap().response.unwrap()
+    }
+
+    #[test]
+    fn create_game_publishes_initial_update() {
+        let (sender, mut updates) = mpsc::unbounded_channel();
+        let engine = InMemoryGameEngine::new(sender);
+        let game_id = GameId::new_v4();
+
+        engine
+            .create_game(
+                RequestId::new_v4(),
+                game_id,
+                PlayerId::new_v4(),
+                ships(&[1, 2]),
+                PlayerId::new_v4(),
+                ships(&[3, 4]),
+            )
+            .unwrap();
+
+        match next_response(&mut updates) {
+            Response::GameUpdate(update) => {
+                assert_eq!(update.game_id, game_id.to_string());
+                assert_eq!(update.status, GameStatus::Player1Turn as i32);
+            }
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[test]
+    fn turns_are_evaluated_until_a_winner() {
+        let (sender, mut updates) = mpsc::unbounded_channel();
+        let engine = InMemoryGameEngine::new(sender);
+        let game_id = GameId::new_v4();
+        let (player1_id, player2_id) = (PlayerId::new_v4(), PlayerId::new_v4());
+
+        engine
+            .create_game(
+                RequestId::new_v4(),
+                game_id,
+                player1_id,
+                ships(&[1, 2]),
+                player2_id,
+                ships(&[3, 4]),
+            )
+            .unwrap();
+        engine
+            .turn(RequestId::new_v4(), game_id, player1_id, 3)
+            .unwrap();
+        engine
+            .turn(RequestId::new_v4(), game_id, player2_id, 50)
+            .unwrap();
+        engine
+            .turn(RequestId::new_v4(), game_id, player1_id, 4)
+            .unwrap();
+
+        let last = std::iter::from_fn(|| updates.try_recv().ok())
+            .last()
+            .unwrap();
+        match last.response.unwrap() {
+            Response::GameUpdate(update) => {
+                assert_eq!(update.status, GameStatus::Player1Win as i32);
+                assert_eq!(update.player1_shots.len(), 2);
+                assert!(update.player1_shots.iter().all(|shot| shot.hit));
+                assert!(!update.player2_shots[0].hit);
+            }
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[test]
+    fn finished_games_are_forgotten() {
+        let (sender, mut updates) = mpsc::unbounded_channel();
+        let engine = InMemoryGameEngine::new(sender);
+        let game_id = GameId::new_v4();
+        let player1_id = PlayerId::new_v4();
+
+        engine
+            .create_game(
+                RequestId::new_v4(),
+                game_id,
+                player1_id,
+                ships(&[1, 2]),
+                PlayerId::new_v4(),
+                ships(&[3, 4]),
+            )
+            .unwrap();
+        engine
+            .resign(RequestId::new_v4(), game_id, player1_id)
+            .unwrap();
+
+        assert!(engine.games.lock().unwrap().is_empty());
+        let last = std::iter::from_fn(|| updates.try_recv().ok())
+            .last()
+            .unwrap();
+        match last.response.unwrap() {
+            Response::GameUpdate(update) => {
+                assert_eq!(update.status, GameStatus::Player2Win as i32)
+            }
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[test]
+    fn turn_in_unknown_game_fails() {
+        let (sender, mut updates) = mpsc::unbounded_channel();
+        let engine = InMemoryGameEngine::new(sender);
+
+        let request_id = RequestId::new_v4();
+        engine
+            .turn(request_id, GameId::new_v4(), PlayerId::new_v4(), 0)
+            .unwrap();
+
+        let message = updates.try_recv().unwrap();
+        assert_eq!(message.request_id, request_id.to_string());
+        match message.response.unwrap() {
+            Response::Failure(failure) => {
+                assert_eq!(failure.code, FailureCode::UnknownGame as i64)
+            }
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+}
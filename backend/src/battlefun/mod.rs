@@ -1,16 +1,33 @@
 use std::collections::HashMap;
+use std::time::SystemTime;
 
 use serde::Serialize;
-use tokio::sync::mpsc;
 use uuid::Uuid;
-use warp::ws::Message;
 
+#[allow(clippy::module_inception)]
 mod battlefun;
 pub use battlefun::BattleFun;
 
+pub mod admin;
+pub mod archive;
+pub mod bot;
+pub mod clock;
+pub mod fleet;
 pub mod gamemaster;
 pub mod kafka;
+pub mod lobby;
 pub mod matchmaking;
+pub mod memory;
+pub mod outbox;
+pub mod protocol;
+pub mod rating;
+pub mod requests;
+pub mod session;
+pub mod shard;
+pub mod stats;
+pub mod store;
+pub mod topics;
+pub mod transport;
 
 pub mod proto {
     include!(concat!(env!("OUT_DIR"), "/io.battlefun.rs"));
@@ -18,7 +35,10 @@ pub mod proto {
 
 pub type GameId = Uuid;
 pub type PlayerId = Uuid;
-pub type PlayerToken = Uuid;
+/// A signed session token; see `session`.
+pub type PlayerToken = String;
+/// Correlates a command sent to the game engine with the engine's reply.
+pub type RequestId = Uuid;
 pub type ShipPlacement = HashMap<String, Vec<CellIndex>>;
 pub type CellIndex = u8;
 
@@ -26,13 +46,20 @@ pub type CellIndex = u8;
 pub struct Player {
     pub id: PlayerId,
     pub name: String,
-    pub token: PlayerToken,
+    pub created_at: SystemTime,
+    pub rating: rating::Rating,
+    pub revocations: session::Revocations,
+    pub role: admin::Role,
+    /// Banned players cannot use their tokens or play.
+    pub banned: bool,
+    /// Set for the server's own computer opponents.
+    pub bot: Option<bot::Difficulty>,
     pub connection: PlayerConnection,
 }
 
 #[derive(Debug, Clone)]
 pub struct PlayerConnection {
-    pub sender: Option<mpsc::UnboundedSender<std::result::Result<Message, warp::Error>>>,
+    pub outbox: Option<outbox::Outbox>,
     pub authenticated: bool,
 }
 
@@ -52,12 +79,37 @@ pub struct PlayerGameState {
     pub opponent_shots: Vec<Shot>,
     pub destroyed_opponent_ships: Vec<String>,
     pub your_ships: ShipPlacement,
+    pub winner_id: Option<PlayerId>,
+    /// How long each player has left of the game time limit, and the player whose turn it is of
+    /// the turn time limit, in milliseconds, where there are such limits.
+    pub your_time_left_ms: Option<u64>,
+    pub opponent_time_left_ms: Option<u64>,
+    pub turn_time_left_ms: Option<u64>,
+}
+
+/// A game as seen by someone watching it: every shot and sunk ship, but where the ships are only
+/// once the game is over.
+#[derive(Serialize, Debug)]
+pub struct SpectatorGameState {
+    pub game_id: GameId,
+    pub player1_id: PlayerId,
+    pub player2_id: PlayerId,
+    pub current_state: i32,
+    pub player1_shots: Vec<Shot>,
+    pub player2_shots: Vec<Shot>,
+    /// The ships of player 1 sunk by player 2, and the other way around.
+    pub destroyed_player1_ships: Vec<String>,
+    pub destroyed_player2_ships: Vec<String>,
+    pub player1_ships: Option<ShipPlacement>,
+    pub player2_ships: Option<ShipPlacement>,
+    pub winner_id: Option<PlayerId>,
 }
 
 trait ToBattleFunProto<P> {
     fn to_proto(&self) -> P;
 }
 
+#[allow(clippy::wrong_self_convention)]
 trait FromBattleFunProto<T> {
     fn from_proto(&self) -> T;
 }
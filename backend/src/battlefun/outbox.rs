@@ -0,0 +1,383 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use futures::future::{self, Either};
+use futures::{pin_mut, Sink, SinkExt};
+use serde::Serialize;
+use tokio::sync::Notify;
+use tokio::time;
+use warp::ws::Message;
+
+use super::GameId;
+
+#[derive(Debug, Clone)]
+pub struct OutboxConfig {
+    /// How many messages may wait to be written to a connection.
+    pub capacity: usize,
+    /// How long a connection's outbox may stay full before the connection is dropped.
+    pub eviction_deadline: Duration,
+}
+
+/// Counts what happened to messages sent to players, across all connections.
+#[derive(Debug, Default)]
+pub struct OutboxMetrics {
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    coalesced: AtomicU64,
+    evicted: AtomicU64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutboxMetricsSnapshot {
+    pub sent: u64,
+    pub dropped: u64,
+    pub coalesced: u64,
+    pub evicted: u64,
+}
+
+impl OutboxMetrics {
+    pub fn snapshot(&self) -> OutboxMetricsSnapshot {
+        OutboxMetricsSnapshot {
+            sent: self.sent.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            evicted: self.evicted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A bounded queue of messages waiting to be written to one player's WebSocket.
+///
+/// A newer snapshot of a game replaces one still waiting. Snapshots of other games pushed while
+/// the outbox is full are dropped, since the next one will bring the player up to date; any other
+/// message that does not fit closes the outbox instead, because the player would miss it for good.
+/// An outbox that stays full for longer than its eviction deadline is closed too. Closing an
+/// outbox disconnects the player.
+#[derive(Clone)]
+pub struct Outbox {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    state: Mutex<State>,
+    ready: Notify,
+    config: OutboxConfig,
+    metrics: Arc<OutboxMetrics>,
+}
+
+#[derive(Default)]
+struct State {
+    queue: VecDeque<Outgoing>,
+    /// When the queue last filled up, if it has not been written to since.
+    full_since: Option<Instant>,
+    closed: bool,
+}
+
+/// Tells apart the snapshots of a game, which supersede any earlier snapshot of the same kind of
+/// the same game: a player's snapshot of their game and a spectator's do not replace each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snapshot {
+    pub kind: &'static str,
+    pub game_id: GameId,
+}
+
+struct Outgoing {
+    snapshot: Option<Snapshot>,
+    message: Message,
+}
+
+impl Outbox {
+    pub fn new(config: OutboxConfig, metrics: Arc<OutboxMetrics>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State::default()),
+                ready: Notify::new(),
+                config,
+                metrics,
+            }),
+        }
+    }
+
+    /// Queues `message`, replacing a waiting message for the same `snapshot` if there is one.
+    pub fn push(&self, message: Message, snapshot: Option<Snapshot>) {
+        let mut state = self.inner.state.lock().unwrap();
+        if state.closed {
+            return;
+        }
+
+        if snapshot.is_some() {
+            let waiting = state
+                .queue
+                .iter_mut()
+                .find(|outgoing| outgoing.snapshot == snapshot);
+            if let Some(waiting) = waiting {
+                waiting.message = message;
+                self.inner.metrics.coalesced.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        }
+
+        let capacity = self.inner.config.capacity;
+        if state.queue.len() < capacity {
+            state.queue.push_back(Outgoing { snapshot, message });
+            if state.queue.len() == capacity {
+                state.full_since.get_or_insert_with(Instant::now);
+            }
+            drop(state);
+            self.inner.ready.notify();
+            return;
+        }
+
+        self.inner.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+        if snapshot.is_none() || self.is_overdue(&state) {
+            self.evict(state);
+        }
+    }
+
+    /// Closes the outbox if it has stayed full past the eviction deadline, returning whether it
+    /// is closed.
+    fn evict_if_overdue(&self) -> bool {
+        let state = self.inner.state.lock().unwrap();
+        if state.closed {
+            return true;
+        }
+
+        if self.is_overdue(&state) {
+            self.evict(state);
+            return true;
+        }
+
+        false
+    }
+
+    fn is_overdue(&self, state: &State) -> bool {
+        state.full_since.map_or(false, |since| {
+            since.elapsed() >= self.inner.config.eviction_deadline
+        })
+    }
+
+    /// Closes the outbox, dropping every message still waiting in it.
+    fn evict(&self, mut state: MutexGuard<State>) {
+        let dropped = state.queue.len() as u64;
+        state.closed = true;
+        state.queue.clear();
+        drop(state);
+
+        self.inner
+            .metrics
+            .dropped
+            .fetch_add(dropped, Ordering::Relaxed);
+        self.inner.metrics.evicted.fetch_add(1, Ordering::Relaxed);
+        self.inner.ready.notify();
+    }
+
+    /// Stops accepting messages and wakes the writer so it can disconnect the player.
+    pub fn close(&self) {
+        self.inner.state.lock().unwrap().closed = true;
+        self.inner.ready.notify();
+    }
+
+    /// Whether `other` is a handle to this same outbox.
+    pub fn is_same(&self, other: &Outbox) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.state.lock().unwrap().closed
+    }
+
+    /// Writes queued messages to `sink` until the outbox is closed or the sink fails.
+    pub async fn drain<S>(&self, mut sink: S)
+    where
+        S: Sink<Message, Error = warp::Error> + Unpin,
+    {
+        loop {
+            let message = match self.pop() {
+                Some(message) => message,
+                None if self.is_closed() => break,
+                None => {
+                    self.inner.ready.notified().await;
+                    continue;
+                }
+            };
+
+            if !self.send(&mut sink, message).await {
+                return;
+            }
+        }
+
+        let _ = sink.close().await;
+    }
+
+    /// Writes `message` to `sink`, giving up if the outbox is closed, or stays full past the
+    /// eviction deadline, while the sink is blocked. Returns whether the sink can be written to
+    /// again.
+    async fn send<S>(&self, sink: &mut S, message: Message) -> bool
+    where
+        S: Sink<Message, Error = warp::Error> + Unpin,
+    {
+        let send = sink.send(message);
+        pin_mut!(send);
+
+        loop {
+            let closed = self.inner.ready.notified();
+            let overdue = time::delay_for(self.inner.config.eviction_deadline);
+            let woken = future::select(Box::pin(closed), overdue);
+
+            match future::select(send.as_mut(), woken).await {
+                Either::Left((Ok(()), _)) => {
+                    self.inner.metrics.sent.fetch_add(1, Ordering::Relaxed);
+                    return true;
+                }
+                Either::Left((Err(e), _)) => {
+                    eprintln!("error sending websocket msg: {}", e);
+                    return false;
+                }
+                Either::Right(_) if self.evict_if_overdue() => return false,
+                Either::Right(_) => continue,
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<Message> {
+        let mut state = self.inner.state.lock().unwrap();
+        if state.closed {
+            return None;
+        }
+
+        let outgoing = state.queue.pop_front()?;
+        state.full_since = None;
+
+        Some(outgoing.message)
+    }
+}
+
+#[cfg(test)]
+impl Outbox {
+    /// Takes every message waiting to be written, as text.
+    pub fn drained(&self) -> Vec<String> {
+        std::iter::from_fn(|| self.pop())
+            .map(|message| message.to_str().unwrap().to_owned())
+            .collect()
+    }
+}
+
+impl fmt::Debug for Outbox {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.inner.state.lock().unwrap();
+
+        f.debug_struct("Outbox")
+            .field("queued", &state.queue.len())
+            .field("closed", &state.closed)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outbox(capacity: usize, eviction_deadline: Duration) -> (Outbox, Arc<OutboxMetrics>) {
+        let metrics = Arc::new(OutboxMetrics::default());
+        let config = OutboxConfig {
+            capacity,
+            eviction_deadline,
+        };
+
+        (Outbox::new(config, metrics.clone()), metrics)
+    }
+
+    fn game_state(game_id: GameId) -> Option<Snapshot> {
+        Some(Snapshot {
+            kind: "game_state",
+            game_id,
+        })
+    }
+
+    #[test]
+    fn coalesces_snapshots_of_the_same_game() {
+        let (outbox, metrics) = outbox(10, Duration::from_secs(10));
+        let (game1, game2) = (GameId::new_v4(), GameId::new_v4());
+
+        outbox.push(Message::text("game1 v1"), game_state(game1));
+        outbox.push(Message::text("chat"), None);
+        outbox.push(Message::text("game2 v1"), game_state(game2));
+        outbox.push(Message::text("game1 v2"), game_state(game1));
+
+        assert_eq!(outbox.drained(), vec!["game1 v2", "chat", "game2 v1"]);
+        assert_eq!(metrics.snapshot().coalesced, 1);
+    }
+
+    #[test]
+    fn keeps_snapshots_of_different_kinds_apart() {
+        let (outbox, metrics) = outbox(10, Duration::from_secs(10));
+        let game_id = GameId::new_v4();
+        let spectator_state = Some(Snapshot {
+            kind: "spectator_state",
+            game_id,
+        });
+
+        outbox.push(Message::text("player v1"), game_state(game_id));
+        outbox.push(Message::text("spectator v1"), spectator_state);
+        outbox.push(Message::text("spectator v2"), spectator_state);
+
+        assert_eq!(outbox.drained(), vec!["player v1", "spectator v2"]);
+        assert_eq!(metrics.snapshot().coalesced, 1);
+    }
+
+    #[test]
+    fn drops_snapshots_while_full() {
+        let (outbox, metrics) = outbox(2, Duration::from_secs(10));
+
+        outbox.push(Message::text("0"), None);
+        for i in 1..4 {
+            outbox.push(Message::text(i.to_string()), game_state(GameId::new_v4()));
+        }
+
+        assert_eq!(outbox.drained(), vec!["0", "1"]);
+        assert_eq!(metrics.snapshot().dropped, 2);
+        assert!(!outbox.is_closed());
+
+        outbox.push(Message::text("4"), None);
+        assert_eq!(outbox.drained(), vec!["4"]);
+    }
+
+    #[test]
+    fn closes_rather_than_drop_other_messages() {
+        let (outbox, metrics) = outbox(1, Duration::from_secs(10));
+
+        outbox.push(Message::text("0"), None);
+        outbox.push(Message::text("1"), None);
+
+        assert!(outbox.is_closed());
+        assert!(outbox.drained().is_empty());
+        assert_eq!(metrics.snapshot().dropped, 2);
+        assert_eq!(metrics.snapshot().evicted, 1);
+    }
+
+    #[test]
+    fn evicts_outboxes_full_past_the_deadline() {
+        let (outbox, metrics) = outbox(1, Duration::from_secs(0));
+
+        outbox.push(Message::text("0"), None);
+        assert!(!outbox.is_closed());
+
+        // The writer finds the outbox overdue while the connection is stuck, with nothing more
+        // pushed to it.
+        assert!(outbox.evict_if_overdue());
+        assert!(outbox.is_closed());
+        assert!(outbox.drained().is_empty());
+        assert_eq!(
+            metrics.snapshot(),
+            OutboxMetricsSnapshot {
+                sent: 0,
+                dropped: 1,
+                coalesced: 0,
+                evicted: 1,
+            }
+        );
+    }
+}
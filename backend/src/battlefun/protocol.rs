@@ -0,0 +1,444 @@
+//! The messages exchanged with players over their WebSocket. Every frame is a JSON object whose
+//! `type` says what kind of message it is and whose `version` says which revision of this protocol
+//! it belongs to.
+
+use serde::{Deserialize, Serialize};
+use warp::ws::Message;
+
+use super::bot::Difficulty;
+use super::fleet::PlacementViolation;
+use super::lobby::{ChallengeId, LobbyCode};
+use super::outbox::{Outbox, Snapshot};
+use super::proto::from_game_fn::Failure;
+use super::stats::{LeaderboardEntry, Period};
+use super::topics::Topic;
+use super::{
+    CellIndex, GameId, PlayerConnection, PlayerGameState, PlayerId, PlayerToken, RequestId,
+    ShipPlacement, SpectatorGameState,
+};
+use crate::error::Error;
+use crate::game::FailureCode;
+
+/// The revision of the protocol this server speaks. Clients may omit `version` from their
+/// messages, in which case they are taken to speak this revision.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The longest chat message a player may send, in characters.
+pub const MAX_CHAT_LENGTH: usize = 500;
+
+#[derive(Deserialize, Debug)]
+pub struct ClientEnvelope {
+    #[serde(default = "current_version")]
+    pub version: u32,
+    #[serde(flatten)]
+    pub message: ClientMessage,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    #[serde(alias = "authenticate")]
+    Authentication {
+        token: PlayerToken,
+    },
+    QueueForGame {
+        ships: ShipPlacement,
+        #[serde(default)]
+        bot_fallback: Option<Difficulty>,
+    },
+    PlayBot {
+        ships: ShipPlacement,
+        difficulty: Difficulty,
+    },
+    LeaveQueue,
+    CreateLobby {
+        ships: ShipPlacement,
+    },
+    JoinLobby {
+        code: LobbyCode,
+        ships: ShipPlacement,
+    },
+    CloseLobby {
+        code: LobbyCode,
+    },
+    Challenge {
+        player_id: PlayerId,
+        ships: ShipPlacement,
+    },
+    AcceptChallenge {
+        challenge_id: ChallengeId,
+        ships: ShipPlacement,
+    },
+    DeclineChallenge {
+        challenge_id: ChallengeId,
+    },
+    TakeShot {
+        game_id: GameId,
+        cell: CellIndex,
+    },
+    Resign {
+        game_id: GameId,
+    },
+    Chat {
+        game_id: GameId,
+        message: String,
+    },
+    Subscribe {
+        topics: Vec<Topic>,
+    },
+    Unsubscribe {
+        topics: Vec<Topic>,
+    },
+    /// Follows a game the player need not be playing in; the same as subscribing to its topic.
+    Spectate {
+        game_id: GameId,
+    },
+    StopSpectating {
+        game_id: GameId,
+    },
+    Ping,
+}
+
+#[derive(Serialize, Debug)]
+struct ServerEnvelope<'a> {
+    version: u32,
+    #[serde(flatten)]
+    message: &'a ServerMessage,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    AuthenticationResponse {
+        success: bool,
+    },
+    GameState {
+        game_state: PlayerGameState,
+    },
+    /// The player has little time left for their turn or their game.
+    ClockWarning {
+        game_id: GameId,
+        remaining_ms: u64,
+    },
+    /// The player ran out of time, and has lost the game. Sent to both players.
+    TimeExpired {
+        game_id: GameId,
+        player_id: PlayerId,
+    },
+    /// A game being watched, as anyone other than its players may see it.
+    SpectatorState {
+        game_state: SpectatorGameState,
+    },
+    Error(ErrorMessage),
+    MatchFound {
+        game_id: GameId,
+        opponent_id: PlayerId,
+        opponent_name: String,
+        opponent_rating: f64,
+    },
+    /// The player waited too long in the matchmaking queue and was taken out of it.
+    QueueExpired,
+    LobbyCreated {
+        code: LobbyCode,
+    },
+    ChallengeSent {
+        challenge_id: ChallengeId,
+        player_id: PlayerId,
+    },
+    /// Another player challenged this one to a game, which they may accept or decline.
+    ChallengeReceived {
+        challenge_id: ChallengeId,
+        challenger_id: PlayerId,
+        challenger_name: String,
+        challenger_rating: f64,
+    },
+    ChallengeDeclined {
+        challenge_id: ChallengeId,
+        player_id: PlayerId,
+    },
+    /// An opponent came online or went offline.
+    Presence {
+        player_id: PlayerId,
+        online: bool,
+    },
+    Chat {
+        game_id: GameId,
+        player_id: PlayerId,
+        name: String,
+        message: String,
+    },
+    Announcement {
+        kind: AnnouncementKind,
+        message: String,
+    },
+    /// A moderator ended the game without a result.
+    GameAbandoned {
+        game_id: GameId,
+    },
+    GameStarted {
+        game_id: GameId,
+        player1_id: PlayerId,
+        player2_id: PlayerId,
+    },
+    GameFinished {
+        game_id: GameId,
+        winner_id: PlayerId,
+    },
+    RatingChanged {
+        player_id: PlayerId,
+        name: String,
+        rating: f64,
+    },
+    /// The top of a leaderboard, after a game finished.
+    Leaderboard {
+        period: Period,
+        entries: Vec<LeaderboardEntry>,
+    },
+    Pong,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnouncementKind {
+    Info,
+    Warning,
+    /// The server is about to go down or has just come back.
+    Maintenance,
+}
+
+impl Default for AnnouncementKind {
+    fn default() -> Self {
+        AnnouncementKind::Info
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ErrorMessage {
+    pub reason: ErrorReason,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_id: Option<GameId>,
+    /// Set when the game engine rejected a command, to the id it was sent with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<RequestId>,
+    /// Set when the game engine rejected a command, to one of statefun's `FailureCodes`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<i64>,
+    /// The name of `code`, if it is one we know.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<FailureCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Vec<PlacementViolation>>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorReason {
+    InvalidMessage,
+    UnsupportedVersion,
+    NotAuthenticated,
+    NoSuchGame,
+    NotQueued,
+    NoSuchPlayer,
+    NoSuchLobby,
+    NoSuchChallenge,
+    Forbidden,
+    InvalidArgument,
+    InvalidShipPlacement,
+    GameRejected,
+    Internal,
+}
+
+impl ErrorMessage {
+    pub fn new(reason: ErrorReason, message: impl Into<String>) -> Self {
+        Self {
+            reason,
+            message: message.into(),
+            game_id: None,
+            request_id: None,
+            code: None,
+            failure: None,
+            details: None,
+        }
+    }
+
+    /// The game engine rejected the command `request_id` sent for `game_id`.
+    pub fn rejected(game_id: GameId, request_id: RequestId, failure: Failure) -> Self {
+        Self {
+            game_id: Some(game_id),
+            request_id: Some(request_id),
+            code: Some(failure.code),
+            failure: FailureCode::from_code(failure.code),
+            ..Self::new(ErrorReason::GameRejected, failure.failure_description)
+        }
+    }
+
+    pub fn in_game(self, game_id: GameId) -> Self {
+        Self {
+            game_id: Some(game_id),
+            ..self
+        }
+    }
+}
+
+impl From<Error> for ErrorMessage {
+    fn from(error: Error) -> Self {
+        let message = error.to_string();
+
+        match error {
+            Error::NoSuchGame(game_id) => {
+                Self::new(ErrorReason::NoSuchGame, message).in_game(game_id)
+            }
+            Error::NotQueued => Self::new(ErrorReason::NotQueued, message),
+            Error::NoSuchPlayer(_) => Self::new(ErrorReason::NoSuchPlayer, message),
+            Error::NoSuchLobby(_) => Self::new(ErrorReason::NoSuchLobby, message),
+            Error::NoSuchChallenge(_) => Self::new(ErrorReason::NoSuchChallenge, message),
+            Error::Unauthorized(_) => Self::new(ErrorReason::NotAuthenticated, message),
+            Error::Forbidden(_) => Self::new(ErrorReason::Forbidden, message),
+            Error::InvalidArgument(_) => Self::new(ErrorReason::InvalidArgument, message),
+            Error::InvalidShipPlacement(violations) => Self {
+                details: Some(violations),
+                ..Self::new(ErrorReason::InvalidShipPlacement, message)
+            },
+            Error::GameEngineFailure(failure) => Self {
+                code: Some(failure.code),
+                failure: FailureCode::from_code(failure.code),
+                ..Self::new(ErrorReason::GameRejected, message)
+            },
+            _ => {
+                eprintln!("unhandled application error: {:?}", error);
+                Self::new(ErrorReason::Internal, "Internal Server Error")
+            }
+        }
+    }
+}
+
+impl PlayerConnection {
+    /// Sends `message` to the player if they are connected.
+    pub fn send(&self, message: &ServerMessage) {
+        if let Some(outbox) = &self.outbox {
+            message.push_to(outbox);
+        }
+    }
+}
+
+impl ServerMessage {
+    /// Queues the message on `outbox`, letting a snapshot of a game replace an older one.
+    pub fn push_to(&self, outbox: &Outbox) {
+        let envelope = ServerEnvelope {
+            version: PROTOCOL_VERSION,
+            message: self,
+        };
+        let json = serde_json::to_string(&envelope).unwrap();
+
+        let snapshot = match self {
+            ServerMessage::GameState { game_state } => Some(Snapshot {
+                kind: "game_state",
+                game_id: game_state.game_id,
+            }),
+            ServerMessage::SpectatorState { game_state } => Some(Snapshot {
+                kind: "spectator_state",
+                game_id: game_state.game_id,
+            }),
+            _ => None,
+        };
+
+        outbox.push(Message::text(json), snapshot);
+    }
+}
+
+fn current_version() -> u32 {
+    PROTOCOL_VERSION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{from_str, json, to_value};
+
+    #[test]
+    fn parses_client_messages() {
+        let game_id = GameId::new_v4();
+
+        let envelope: ClientEnvelope = from_str(&format!(
+            r#"{{"type": "take_shot", "game_id": "{}", "cell": 42}}"#,
+            game_id
+        ))
+        .unwrap();
+        assert_eq!(envelope.version, PROTOCOL_VERSION);
+        match envelope.message {
+            ClientMessage::TakeShot { game_id: id, cell } => {
+                assert_eq!(id, game_id);
+                assert_eq!(cell, 42);
+            }
+            message => panic!("unexpected message: {:?}", message),
+        }
+
+        let envelope: ClientEnvelope = from_str(r#"{"type": "ping", "version": 2}"#).unwrap();
+        assert_eq!(envelope.version, 2);
+        assert!(matches!(envelope.message, ClientMessage::Ping));
+
+        let envelope: ClientEnvelope =
+            from_str(r#"{"type": "subscribe", "topics": ["lobby", "leaderboard"]}"#).unwrap();
+        match envelope.message {
+            ClientMessage::Subscribe { topics } => {
+                assert_eq!(topics, vec![Topic::Lobby, Topic::Leaderboard])
+            }
+            message => panic!("unexpected message: {:?}", message),
+        }
+
+        assert!(from_str::<ClientEnvelope>(r#"{"type": "launch_missiles"}"#).is_err());
+        assert!(from_str::<ClientEnvelope>(r#"{"type": "subscribe", "topics": ["x"]}"#).is_err());
+    }
+
+    #[test]
+    fn accepts_both_authentication_spellings() {
+        let token: PlayerToken = "header.claims.signature".to_owned();
+
+        for r#type in &["authentication", "authenticate"] {
+            let envelope: ClientEnvelope =
+                from_str(&json!({ "type": r#type, "token": token }).to_string()).unwrap();
+            match envelope.message {
+                ClientMessage::Authentication { token: t } => assert_eq!(t, token),
+                message => panic!("unexpected message: {:?}", message),
+            }
+        }
+    }
+
+    #[test]
+    fn tags_server_messages_with_type_and_version() {
+        let envelope = ServerEnvelope {
+            version: PROTOCOL_VERSION,
+            message: &ServerMessage::Pong,
+        };
+        assert_eq!(
+            to_value(&envelope).unwrap(),
+            json!({ "type": "pong", "version": PROTOCOL_VERSION })
+        );
+
+        let game_id = GameId::new_v4();
+        let request_id = RequestId::new_v4();
+        let error = ServerMessage::Error(ErrorMessage::rejected(
+            game_id,
+            request_id,
+            FailureCode::NotPlayersTurn.failure("Not your turn"),
+        ));
+        assert_eq!(
+            to_value(&ServerEnvelope {
+                version: PROTOCOL_VERSION,
+                message: &error,
+            })
+            .unwrap(),
+            json!({
+                "type": "error",
+                "version": PROTOCOL_VERSION,
+                "reason": "game_rejected",
+                "message": "Not your turn",
+                "game_id": game_id,
+                "request_id": request_id,
+                "code": 2,
+                "failure": "NOT_PLAYERS_TURN",
+            })
+        );
+    }
+}
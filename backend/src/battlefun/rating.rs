@@ -0,0 +1,93 @@
+//! Elo ratings, updated from the result of every finished game.
+
+use serde::{Deserialize, Serialize};
+
+/// The rating every player starts with.
+pub const INITIAL_RATING: f64 = 1500.0;
+
+/// How far a single game moves the ratings of players who have finished fewer than
+/// `PROVISIONAL_GAMES` games, so that new players quickly find their level.
+const PROVISIONAL_K: f64 = 40.0;
+const PROVISIONAL_GAMES: u32 = 20;
+/// How far a single game moves the ratings of established players.
+const K: f64 = 20.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub value: f64,
+    /// How many rated games the player has finished.
+    pub games: u32,
+}
+
+impl Rating {
+    /// The chance that a player with this rating beats one rated `opponent`.
+    pub fn expected_score(&self, opponent: &Rating) -> f64 {
+        1.0 / (1.0 + 10f64.powf((opponent.value - self.value) / 400.0))
+    }
+
+    /// Records the result of a game against `opponent`.
+    pub fn record(&mut self, opponent: &Rating, won: bool) {
+        let score = if won { 1.0 } else { 0.0 };
+        let k = if self.games < PROVISIONAL_GAMES {
+            PROVISIONAL_K
+        } else {
+            K
+        };
+
+        self.value += k * (score - self.expected_score(opponent));
+        self.games += 1;
+    }
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            value: INITIAL_RATING,
+            games: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(value: f64, games: u32) -> Rating {
+        Rating { value, games }
+    }
+
+    #[test]
+    fn evenly_matched_players_trade_half_of_k() {
+        let (mut winner, mut loser) = (rating(1500.0, 50), rating(1500.0, 50));
+        let (winner_before, loser_before) = (winner, loser);
+
+        winner.record(&loser_before, true);
+        loser.record(&winner_before, false);
+
+        assert_eq!(winner, rating(1510.0, 51));
+        assert_eq!(loser, rating(1490.0, 51));
+    }
+
+    #[test]
+    fn upsets_move_ratings_further_than_expected_results() {
+        let (favourite, underdog) = (rating(1800.0, 50), rating(1400.0, 50));
+
+        let mut expected = favourite;
+        expected.record(&underdog, true);
+        let mut upset = underdog;
+        upset.record(&favourite, true);
+
+        assert!(expected.value - favourite.value < 2.0);
+        assert!(upset.value - underdog.value > 18.0);
+    }
+
+    #[test]
+    fn new_players_move_faster() {
+        let opponent = rating(1500.0, 50);
+
+        let mut new = rating(1500.0, 0);
+        new.record(&opponent, true);
+
+        assert_eq!(new.value, 1520.0);
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+
+use super::proto::from_game_fn::Response;
+use super::{PlayerId, RequestId};
+
+/// How long a request may go unanswered before it is forgotten.
+const REQUEST_TTL: Duration = Duration::from_secs(60);
+
+/// Remembers which player sent each command to the game engine, so that the engine's reply can be
+/// routed back to them and to anyone waiting on it.
+pub struct PendingRequests {
+    requests: Mutex<HashMap<RequestId, PendingRequest>>,
+}
+
+struct PendingRequest {
+    player_id: PlayerId,
+    created_at: Instant,
+    reply: oneshot::Sender<Response>,
+}
+
+impl PendingRequests {
+    pub fn new() -> Self {
+        Self {
+            requests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns a new request id on behalf of `player_id`, and a receiver for the engine's reply to
+    /// it, which may be dropped if nobody needs to wait.
+    pub fn register(&self, player_id: PlayerId) -> (RequestId, oneshot::Receiver<Response>) {
+        let request_id = RequestId::new_v4();
+        let (reply, receiver) = oneshot::channel();
+
+        self.requests.lock().unwrap().insert(
+            request_id,
+            PendingRequest {
+                player_id,
+                created_at: Instant::now(),
+                reply,
+            },
+        );
+
+        (request_id, receiver)
+    }
+
+    /// Hands `response` to whoever is waiting on `request_id` and forgets it, returning the player
+    /// who sent it if it was still pending.
+    pub fn complete(&self, request_id: &RequestId, response: &Response) -> Option<PlayerId> {
+        let request = self.requests.lock().unwrap().remove(request_id)?;
+        let _ = request.reply.send(response.clone());
+
+        Some(request.player_id)
+    }
+
+    /// Forgets the requests that have gone unanswered for longer than `REQUEST_TTL`.
+    pub fn expire(&self) {
+        let now = Instant::now();
+        self.requests
+            .lock()
+            .unwrap()
+            .retain(|_, request| now.duration_since(request.created_at) < REQUEST_TTL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::game::FailureCode;
+
+    fn failure() -> Response {
+        Response::Failure(FailureCode::NotPlayersTurn.failure("Not your turn"))
+    }
+
+    #[test]
+    fn completes_each_request_once() {
+        let requests = PendingRequests::new();
+        let player_id = PlayerId::new_v4();
+
+        let (request_id, mut receiver) = requests.register(player_id);
+
+        assert_eq!(requests.complete(&request_id, &failure()), Some(player_id));
+        assert_eq!(receiver.try_recv(), Ok(failure()));
+        assert_eq!(requests.complete(&request_id, &failure()), None);
+        assert_eq!(requests.complete(&RequestId::new_v4(), &failure()), None);
+    }
+
+    #[test]
+    fn completes_requests_nobody_waits_on() {
+        let requests = PendingRequests::new();
+        let player_id = PlayerId::new_v4();
+
+        let (request_id, receiver) = requests.register(player_id);
+        drop(receiver);
+
+        assert_eq!(requests.complete(&request_id, &failure()), Some(player_id));
+    }
+
+    #[test]
+    fn keeps_fresh_requests_when_expiring() {
+        let requests = PendingRequests::new();
+        let player_id = PlayerId::new_v4();
+
+        let (request_id, _) = requests.register(player_id);
+        requests.expire();
+
+        assert_eq!(requests.complete(&request_id, &failure()), Some(player_id));
+    }
+}
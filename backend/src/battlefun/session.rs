@@ -0,0 +1,232 @@
+//! Session tokens, which players present to prove who they are. A token is a JWT signed with
+//! HMAC-SHA256, so the backend can check it without keeping a list of the tokens it has issued;
+//! it only has to remember the tokens revoked before they expire.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac, NewMac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use super::{PlayerId, PlayerToken};
+use crate::error::Error;
+
+/// Names a single token, so that it can be revoked on its own.
+pub type SessionId = Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+/// What a token says about its bearer. Times are in seconds since the Unix epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Claims {
+    /// The player the token was issued to.
+    pub sub: PlayerId,
+    pub jti: SessionId,
+    pub iat: u64,
+    pub exp: u64,
+    /// The player's revocation generation when the token was issued; see `Revocations`.
+    pub gen: u32,
+}
+
+/// Issues and checks tokens.
+pub struct Sessions {
+    key: Vec<u8>,
+    ttl: Duration,
+}
+
+impl Sessions {
+    /// Signs tokens with `secret`, or with a random key if there is none, in which case no token
+    /// outlives the process.
+    pub fn new(secret: Option<&str>, ttl: Duration) -> Self {
+        let key = match secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                eprintln!(
+                    "TOKEN_SECRET is not set or empty; tokens will not be valid after a restart"
+                );
+                let mut key = vec![0; 32];
+                rand::thread_rng().fill_bytes(&mut key);
+                key
+            }
+        };
+
+        Self { key, ttl }
+    }
+
+    /// Issues a token for `player_id`, valid from now until the TTL has passed.
+    pub fn issue(&self, player_id: PlayerId, gen: u32) -> (PlayerToken, Claims) {
+        let iat = now();
+        let claims = Claims {
+            sub: player_id,
+            jti: SessionId::new_v4(),
+            iat,
+            exp: iat + self.ttl.as_secs(),
+            gen,
+        };
+
+        let signing_input = format!(
+            "{}.{}",
+            encode(HEADER.as_bytes()),
+            encode(&serde_json::to_vec(&claims).unwrap())
+        );
+        let signature = encode(&self.mac(&signing_input).finalize().into_bytes());
+
+        (format!("{}.{}", signing_input, signature), claims)
+    }
+
+    /// Returns what the token says, if it was signed here and has not expired. Whether it has
+    /// been revoked is up to the caller.
+    pub fn verify(&self, token: &str) -> Result<Claims, Error> {
+        self.verify_at(token, now())
+    }
+
+    fn verify_at(&self, token: &str, now: u64) -> Result<Claims, Error> {
+        let (signing_input, signature) = match token.rfind('.') {
+            Some(dot) => (&token[..dot], &token[dot + 1..]),
+            None => return Err(invalid_token()),
+        };
+
+        let signature = decode(signature)?;
+        self.mac(signing_input)
+            .verify(&signature)
+            .map_err(|_| invalid_token())?;
+
+        let payload = match signing_input.split_once('.') {
+            Some((header, payload)) if decode(header)? == HEADER.as_bytes() => payload,
+            _ => return Err(invalid_token()),
+        };
+        let claims: Claims =
+            serde_json::from_slice(&decode(payload)?).map_err(|_| invalid_token())?;
+
+        if claims.exp <= now {
+            return Err(invalid_token());
+        }
+
+        Ok(claims)
+    }
+
+    fn mac(&self, signing_input: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_varkey(&self.key).expect("HMAC takes keys of any length");
+        mac.update(signing_input.as_bytes());
+        mac
+    }
+}
+
+/// The tokens of a player that are no longer accepted even though they have not expired.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Revocations {
+    /// Bumped whenever the player revokes all of their tokens, which leaves every token issued
+    /// before with an older generation.
+    pub generation: u32,
+    /// Tokens revoked one at a time, with when they expire, after which they can be forgotten.
+    pub tokens: HashMap<SessionId, u64>,
+}
+
+impl Revocations {
+    pub fn allows(&self, claims: &Claims) -> bool {
+        claims.gen == self.generation && !self.tokens.contains_key(&claims.jti)
+    }
+
+    pub fn revoke(&mut self, claims: &Claims) {
+        let now = now();
+        self.tokens.retain(|_, &mut exp| exp > now);
+        self.tokens.insert(claims.jti, claims.exp);
+    }
+
+    pub fn revoke_all(&mut self) {
+        self.generation += 1;
+        self.tokens.clear();
+    }
+}
+
+fn invalid_token() -> Error {
+    Error::Unauthorized("invalid or expired token".to_owned())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(part: &str) -> Result<Vec<u8>, Error> {
+    base64::decode_config(part, base64::URL_SAFE_NO_PAD).map_err(|_| invalid_token())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sessions() -> Sessions {
+        Sessions::new(Some("secret"), Duration::from_secs(60))
+    }
+
+    #[test]
+    fn verifies_its_own_tokens_until_they_expire() {
+        let sessions = sessions();
+        let player_id = PlayerId::new_v4();
+
+        let (token, claims) = sessions.issue(player_id, 0);
+        assert_eq!(token.split('.').count(), 3);
+        assert_eq!(sessions.verify(&token).unwrap(), claims);
+        assert_eq!(claims.sub, player_id);
+
+        assert!(sessions.verify_at(&token, claims.exp - 1).is_ok());
+        assert!(sessions.verify_at(&token, claims.exp).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_and_foreign_tokens() {
+        let sessions = sessions();
+        let (token, claims) = sessions.issue(PlayerId::new_v4(), 0);
+
+        let other = Claims {
+            sub: PlayerId::new_v4(),
+            ..claims
+        };
+        let parts: Vec<&str> = token.split('.').collect();
+        let forged = format!(
+            "{}.{}.{}",
+            parts[0],
+            encode(&serde_json::to_vec(&other).unwrap()),
+            parts[2]
+        );
+        assert!(sessions.verify(&forged).is_err());
+
+        let (foreign, _) =
+            Sessions::new(Some("other"), Duration::from_secs(60)).issue(PlayerId::new_v4(), 0);
+        assert!(sessions.verify(&foreign).is_err());
+
+        for garbage in &["", "...", "not a token", &token[..token.len() - 2]] {
+            assert!(sessions.verify(garbage).is_err());
+        }
+    }
+
+    #[test]
+    fn revokes_single_tokens_or_all_of_them() {
+        let sessions = sessions();
+        let player_id = PlayerId::new_v4();
+        let mut revocations = Revocations::default();
+
+        let (_, first) = sessions.issue(player_id, revocations.generation);
+        let (_, second) = sessions.issue(player_id, revocations.generation);
+        revocations.revoke(&first);
+        assert!(!revocations.allows(&first));
+        assert!(revocations.allows(&second));
+
+        revocations.revoke_all();
+        assert!(!revocations.allows(&second));
+        let (_, third) = sessions.issue(player_id, revocations.generation);
+        assert!(revocations.allows(&third));
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::RwLock;
+
+const SHARDS: usize = 16;
+
+/// A map split into independently locked shards, so that callers working on different keys rarely
+/// contend. Entries are only reachable through closures or clones, which keeps each lock to a
+/// short critical section that can never be held across an `.await`.
+pub struct ShardedMap<K, V> {
+    shards: Vec<RwLock<HashMap<K, V>>>,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V> ShardedMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| RwLock::new(HashMap::new())).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.shard(&key).write().unwrap().insert(key, value)
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.shard(key).write().unwrap().remove(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.shard(key).read().unwrap().contains_key(key)
+    }
+
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.with(key, V::clone)
+    }
+
+    /// Calls `f` with the value for `key`, if there is one.
+    pub fn with<R>(&self, key: &K, f: impl FnOnce(&V) -> R) -> Option<R> {
+        self.shard(key).read().unwrap().get(key).map(f)
+    }
+
+    /// Calls `f` with a mutable reference to the value for `key`, if there is one.
+    pub fn update<R>(&self, key: &K, f: impl FnOnce(&mut V) -> R) -> Option<R> {
+        self.shard(key).write().unwrap().get_mut(key).map(f)
+    }
+
+    /// Calls `f` with every entry, locking one shard at a time.
+    pub fn for_each(&self, mut f: impl FnMut(&K, &V)) {
+        for shard in &self.shards {
+            for (key, value) in shard.read().unwrap().iter() {
+                f(key, value);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().unwrap().len())
+            .sum()
+    }
+
+    fn shard(&self, key: &K) -> &RwLock<HashMap<K, V>> {
+        let mut hasher = self.hasher.build_hasher();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARDS]
+    }
+}
+
+impl<K: Hash + Eq, V> Default for ShardedMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_entries_across_shards() {
+        let map = ShardedMap::new();
+
+        for i in 0..100 {
+            assert_eq!(map.insert(i, i * 2), None);
+        }
+
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.get(&21), Some(42));
+        assert_eq!(map.update(&21, |value| *value += 1), Some(()));
+        assert_eq!(map.with(&21, |value| *value), Some(43));
+        assert_eq!(map.remove(&21), Some(43));
+        assert!(!map.contains_key(&21));
+        assert_eq!(map.update(&21, |value| *value += 1), None);
+
+        let mut sum = 0;
+        map.for_each(|_, value| sum += value);
+        assert_eq!(sum, (0..100).map(|i| i * 2).sum::<i32>() - 42);
+    }
+}
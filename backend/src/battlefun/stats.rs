@@ -0,0 +1,311 @@
+//! Statistics on how players have fared, worked out from the games in the archive.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use super::archive::ArchivedGame;
+use super::store::seconds_since_epoch;
+use super::PlayerId;
+use crate::error::Error;
+
+/// How many places of each leaderboard are pushed to its subscribers.
+pub const PUSHED_PLACES: usize = 10;
+
+/// A player's record over every archived game they played.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PlayerStats {
+    pub games: u32,
+    pub wins: u32,
+    pub losses: u32,
+    /// Games the player lost by resigning.
+    pub resignations: u32,
+    pub shots: u32,
+    pub hits: u32,
+    /// The share of the player's shots that hit, if they have taken any.
+    pub accuracy: Option<f64>,
+    /// How many shots the player took on average to sink every ship of an opponent, over the
+    /// games they won that way.
+    pub average_shots_to_win: Option<f64>,
+    /// The number of games the player has won in a row, or lost in a row if negative, up to their
+    /// latest game.
+    pub current_streak: i32,
+    pub longest_winning_streak: u32,
+}
+
+impl PlayerStats {
+    pub fn new(player_id: PlayerId, games: &[ArchivedGame]) -> Self {
+        let mut games: Vec<&ArchivedGame> =
+            games.iter().filter(|g| g.has_player(player_id)).collect();
+        games.sort_by_key(|game| game.finished_at);
+
+        let mut stats = Self::default();
+        let mut shots_to_win = Vec::new();
+
+        for game in games {
+            let won = game.winner_id == player_id;
+            let shots = game.moves.iter().filter(|m| m.player_id == player_id);
+            let (taken, hit) =
+                shots.fold((0, 0), |(taken, hit), m| (taken + 1, hit + m.hit as u32));
+
+            stats.games += 1;
+            stats.shots += taken;
+            stats.hits += hit;
+
+            if won {
+                stats.wins += 1;
+                stats.current_streak = stats.current_streak.max(0) + 1;
+                stats.longest_winning_streak = stats
+                    .longest_winning_streak
+                    .max(stats.current_streak as u32);
+                if !game.resigned && !game.timed_out {
+                    shots_to_win.push(taken);
+                }
+            } else {
+                stats.losses += 1;
+                stats.current_streak = stats.current_streak.min(0) - 1;
+                if game.resigned {
+                    stats.resignations += 1;
+                }
+            }
+        }
+
+        if stats.shots > 0 {
+            stats.accuracy = Some(stats.hits as f64 / stats.shots as f64);
+        }
+        if !shots_to_win.is_empty() {
+            let total: u32 = shots_to_win.iter().sum();
+            stats.average_shots_to_win = Some(total as f64 / shots_to_win.len() as f64);
+        }
+
+        stats
+    }
+}
+
+/// The stretch of time a leaderboard covers, ending now.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Period {
+    /// The last 24 hours.
+    Daily,
+    /// The last 7 days.
+    Weekly,
+    AllTime,
+}
+
+impl Period {
+    pub const ALL: [Period; 3] = [Period::Daily, Period::Weekly, Period::AllTime];
+
+    /// When the period began, in seconds since the Unix epoch.
+    pub fn start(self) -> u64 {
+        let length = match self {
+            Period::Daily => 24 * 60 * 60,
+            Period::Weekly => 7 * 24 * 60 * 60,
+            Period::AllTime => return 0,
+        };
+
+        seconds_since_epoch(SystemTime::now()).saturating_sub(length)
+    }
+}
+
+impl FromStr for Period {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "daily" => Ok(Period::Daily),
+            "weekly" => Ok(Period::Weekly),
+            "all_time" => Ok(Period::AllTime),
+            _ => Err(Error::InvalidArgument(format!("unknown period: {}", s))),
+        }
+    }
+}
+
+/// How many games a player won and lost.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub wins: u32,
+    pub losses: u32,
+}
+
+/// Counts the wins and losses of every player over `games`.
+pub fn tally<'a>(games: impl IntoIterator<Item = &'a ArchivedGame>) -> HashMap<PlayerId, Tally> {
+    let mut tallies: HashMap<PlayerId, Tally> = HashMap::new();
+
+    for game in games {
+        for player_id in [game.player1_id, game.player2_id] {
+            let tally = tallies.entry(player_id).or_default();
+            if player_id == game.winner_id {
+                tally.wins += 1;
+            } else {
+                tally.losses += 1;
+            }
+        }
+    }
+
+    tallies
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LeaderboardEntry {
+    pub rank: usize,
+    pub player_id: PlayerId,
+    pub name: String,
+    pub rating: f64,
+    pub wins: u32,
+    pub losses: u32,
+}
+
+/// Ranks `entries` for the period: all time by rating, and otherwise by wins, then by fewest
+/// losses, then by rating. Keeps the first `limit`.
+pub fn rank(
+    period: Period,
+    mut entries: Vec<LeaderboardEntry>,
+    limit: usize,
+) -> Vec<LeaderboardEntry> {
+    let by_rating = |a: &LeaderboardEntry, b: &LeaderboardEntry| {
+        b.rating.partial_cmp(&a.rating).unwrap_or(Ordering::Equal)
+    };
+    match period {
+        Period::AllTime => entries.sort_by(by_rating),
+        Period::Daily | Period::Weekly => entries.sort_by(|a, b| {
+            b.wins
+                .cmp(&a.wins)
+                .then(a.losses.cmp(&b.losses))
+                .then_with(|| by_rating(a, b))
+        }),
+    }
+
+    entries.truncate(limit);
+    for (i, entry) in entries.iter_mut().enumerate() {
+        entry.rank = i + 1;
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::battlefun::archive::Move;
+    use crate::battlefun::GameId;
+
+    /// A game between `winner_id` and `loser_id`, in which each took the shots given, hitting
+    /// with those marked `true`.
+    fn game(
+        winner_id: PlayerId,
+        loser_id: PlayerId,
+        winner_shots: &[bool],
+        loser_shots: &[bool],
+        resigned: bool,
+        finished_at: u64,
+    ) -> ArchivedGame {
+        let shots = |player_id, hits: &[bool]| -> Vec<Move> {
+            hits.iter()
+                .map(|&hit| Move {
+                    player_id,
+                    cell: 0,
+                    hit,
+                    sunk: None,
+                })
+                .collect()
+        };
+        let mut moves = shots(winner_id, winner_shots);
+        moves.extend(shots(loser_id, loser_shots));
+
+        ArchivedGame {
+            game_id: GameId::new_v4(),
+            player1_id: winner_id,
+            player2_id: loser_id,
+            player1_ships: Default::default(),
+            player2_ships: Default::default(),
+            moves,
+            winner_id,
+            resigned,
+            timed_out: false,
+            started_at: finished_at,
+            finished_at,
+        }
+    }
+
+    #[test]
+    fn works_out_a_players_record() {
+        let (alice, bob) = (PlayerId::new_v4(), PlayerId::new_v4());
+        let games = vec![
+            game(alice, bob, &[true, true, false, true], &[false], false, 1),
+            game(bob, alice, &[true], &[false, false], true, 2),
+            game(alice, bob, &[true, true], &[true], false, 3),
+            game(alice, bob, &[false], &[false], true, 4),
+        ];
+
+        let stats = PlayerStats::new(alice, &games);
+        assert_eq!((stats.games, stats.wins, stats.losses), (4, 3, 1));
+        assert_eq!(stats.resignations, 1);
+        assert_eq!((stats.shots, stats.hits), (9, 5));
+        assert_eq!(stats.accuracy, Some(5.0 / 9.0));
+        assert_eq!(stats.average_shots_to_win, Some(3.0));
+        assert_eq!(stats.current_streak, 2);
+        assert_eq!(stats.longest_winning_streak, 2);
+
+        let stats = PlayerStats::new(bob, &games);
+        assert_eq!((stats.wins, stats.losses, stats.resignations), (1, 3, 1));
+        assert_eq!(stats.current_streak, -2);
+        assert_eq!(stats.average_shots_to_win, None);
+
+        assert_eq!(
+            PlayerStats::new(PlayerId::new_v4(), &games),
+            PlayerStats::default()
+        );
+
+        // Running out of time is neither a resignation nor a fleet sunk.
+        let mut games = games;
+        let mut timed_out = game(alice, bob, &[true], &[false], false, 5);
+        timed_out.timed_out = true;
+        games.push(timed_out);
+
+        let stats = PlayerStats::new(alice, &games);
+        assert_eq!(stats.average_shots_to_win, Some(3.0));
+        let stats = PlayerStats::new(bob, &games);
+        assert_eq!((stats.losses, stats.resignations), (4, 1));
+    }
+
+    #[test]
+    fn ranks_leaderboards_for_their_period() {
+        let entry = |name: &str, rating, wins, losses| LeaderboardEntry {
+            rank: 0,
+            player_id: PlayerId::new_v4(),
+            name: name.to_owned(),
+            rating,
+            wins,
+            losses,
+        };
+        let entries = vec![
+            entry("alice", 1600.0, 2, 3),
+            entry("bob", 1550.0, 2, 1),
+            entry("carol", 1500.0, 4, 0),
+        ];
+        let names = |ranked: Vec<LeaderboardEntry>| -> Vec<(usize, String)> {
+            ranked.into_iter().map(|e| (e.rank, e.name)).collect()
+        };
+
+        assert_eq!(
+            names(rank(Period::AllTime, entries.clone(), 10)),
+            vec![
+                (1, "alice".to_owned()),
+                (2, "bob".to_owned()),
+                (3, "carol".to_owned())
+            ]
+        );
+        assert_eq!(
+            names(rank(Period::Weekly, entries, 2)),
+            vec![(1, "carol".to_owned()), (2, "bob".to_owned())]
+        );
+
+        assert_eq!("all_time".parse::<Period>().unwrap(), Period::AllTime);
+        assert!("monthly".parse::<Period>().is_err());
+    }
+}
@@ -0,0 +1,338 @@
+//! Where registered players, games in progress and finished games are kept, so that they outlive
+//! the process.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hash;
+use std::io::{BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::admin::Role;
+use super::archive::ArchivedGame;
+use super::rating::Rating;
+use super::session::Revocations;
+use super::{GameId, Player, PlayerConnection, PlayerId};
+use crate::error::Error;
+
+/// What is kept of a player between runs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerRecord {
+    pub id: PlayerId,
+    pub name: String,
+    /// Seconds since the Unix epoch.
+    pub created_at: u64,
+    pub rating: Rating,
+    pub revocations: Revocations,
+    pub role: Role,
+    pub banned: bool,
+}
+
+impl PlayerRecord {
+    pub fn from_player(player: &Player) -> Self {
+        Self {
+            id: player.id,
+            name: player.name.clone(),
+            created_at: seconds_since_epoch(player.created_at),
+            rating: player.rating,
+            revocations: player.revocations.clone(),
+            role: player.role,
+            banned: player.banned,
+        }
+    }
+
+    pub fn into_player(self) -> Player {
+        Player {
+            id: self.id,
+            name: self.name,
+            created_at: UNIX_EPOCH + Duration::from_secs(self.created_at),
+            rating: self.rating,
+            revocations: self.revocations,
+            role: self.role,
+            banned: self.banned,
+            bot: None,
+            connection: PlayerConnection {
+                outbox: None,
+                authenticated: false,
+            },
+        }
+    }
+}
+
+/// A game still being played, kept so that its players can carry on after a restart. The game
+/// itself lives in the game engine; this is only what the backend needs to find it again.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GameRecord {
+    pub game_id: GameId,
+    pub player1_id: PlayerId,
+    pub player2_id: PlayerId,
+    /// Seconds since the Unix epoch.
+    pub started_at: u64,
+}
+
+pub fn seconds_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Somewhere records are kept, each replacing any earlier record with the same key.
+pub trait Store<T: Record>: Send + Sync {
+    /// Every record saved and not since removed.
+    fn load(&self) -> Result<Vec<T>, Error>;
+
+    fn save(&self, record: &T) -> Result<(), Error>;
+
+    fn remove(&self, key: &T::Key) -> Result<(), Error>;
+}
+
+pub type PlayerStore = dyn Store<PlayerRecord>;
+pub type GameStore = dyn Store<GameRecord>;
+pub type ArchiveStore = dyn Store<ArchivedGame>;
+
+pub trait Record: Serialize + DeserializeOwned + Clone + Send {
+    type Key: Serialize + DeserializeOwned + Hash + Eq + Copy + Send;
+
+    fn key(&self) -> Self::Key;
+}
+
+impl Record for PlayerRecord {
+    type Key = PlayerId;
+
+    fn key(&self) -> PlayerId {
+        self.id
+    }
+}
+
+impl Record for GameRecord {
+    type Key = GameId;
+
+    fn key(&self) -> GameId {
+        self.game_id
+    }
+}
+
+/// Keeps records for as long as the process runs.
+pub struct InMemoryStore<T: Record> {
+    records: Mutex<HashMap<T::Key, T>>,
+}
+
+impl<T: Record> InMemoryStore<T> {
+    pub fn new() -> Self {
+        Self {
+            records: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Record> Default for InMemoryStore<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Record> Store<T> for InMemoryStore<T> {
+    fn load(&self) -> Result<Vec<T>, Error> {
+        Ok(self.records.lock().unwrap().values().cloned().collect())
+    }
+
+    fn save(&self, record: &T) -> Result<(), Error> {
+        self.records
+            .lock()
+            .unwrap()
+            .insert(record.key(), record.clone());
+        Ok(())
+    }
+
+    fn remove(&self, key: &T::Key) -> Result<(), Error> {
+        self.records.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// Keeps records in a file of JSON lines, each saving or removing one record. Changes are
+/// appended as they happen, and the file is compacted to one line per record when it is opened.
+pub struct FileStore<T: Record> {
+    path: PathBuf,
+    file: Mutex<File>,
+    _records: PhantomData<fn() -> T>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "snake_case", bound = "")]
+enum Change<T: Record> {
+    Save { record: T },
+    Remove { id: T::Key },
+}
+
+impl<T: Record> FileStore<T> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_owned();
+
+        let records: Vec<T> = replay(&path)?;
+        let compacted = path.with_extension("compacting");
+        {
+            let mut file = File::create(&compacted)?;
+            for record in records {
+                append(&mut file, &Change::Save { record })?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&compacted, &path)?;
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            _records: PhantomData,
+        })
+    }
+}
+
+impl<T: Record> Store<T> for FileStore<T> {
+    fn load(&self) -> Result<Vec<T>, Error> {
+        let _file = self.file.lock().unwrap();
+        replay(&self.path)
+    }
+
+    fn save(&self, record: &T) -> Result<(), Error> {
+        let change = Change::Save {
+            record: record.clone(),
+        };
+        append(&mut self.file.lock().unwrap(), &change)
+    }
+
+    fn remove(&self, key: &T::Key) -> Result<(), Error> {
+        append(
+            &mut self.file.lock().unwrap(),
+            &Change::<T>::Remove { id: *key },
+        )
+    }
+}
+
+fn append<T: Record>(file: &mut File, change: &Change<T>) -> Result<(), Error> {
+    let mut line = serde_json::to_string(change).unwrap();
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+
+    Ok(())
+}
+
+/// Reads the records saved in `path`, which need not exist yet. Lines that cannot be parsed, such
+/// as one cut short by a crash, are skipped.
+fn replay<T: Record>(path: &Path) -> Result<Vec<T>, Error> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut records = HashMap::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        match serde_json::from_str::<Change<T>>(&line?) {
+            Ok(Change::Save { record }) => {
+                records.insert(record.key(), record);
+            }
+            Ok(Change::Remove { id }) => {
+                records.remove(&id);
+            }
+            Err(e) => eprintln!(
+                "{}:{}: skipping bad line: {}",
+                path.display(),
+                number + 1,
+                e
+            ),
+        }
+    }
+
+    Ok(records.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str) -> PlayerRecord {
+        PlayerRecord {
+            id: PlayerId::new_v4(),
+            name: name.to_owned(),
+            created_at: 1_600_000_000,
+            rating: Rating::default(),
+            revocations: Revocations::default(),
+            role: Role::default(),
+            banned: false,
+        }
+    }
+
+    fn sorted(mut records: Vec<PlayerRecord>) -> Vec<PlayerRecord> {
+        records.sort_by(|a, b| a.name.cmp(&b.name));
+        records
+    }
+
+    #[test]
+    fn file_store_survives_reopening() {
+        let path = std::env::temp_dir().join(format!("players-{}.jsonl", PlayerId::new_v4()));
+        let (alice, bob, mut carol) = (record("alice"), record("bob"), record("carol"));
+
+        {
+            let store = FileStore::open(&path).unwrap();
+            assert!(store.load().unwrap().is_empty());
+
+            store.save(&alice).unwrap();
+            store.save(&bob).unwrap();
+            store.save(&carol).unwrap();
+            store.remove(&bob.id).unwrap();
+            carol.rating.value = 1600.0;
+            store.save(&carol).unwrap();
+        }
+
+        // A write cut short by a crash.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"op\": \"save\", \"rec").unwrap();
+
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(sorted(store.load().unwrap()), vec![alice.clone(), carol]);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn memory_store_replaces_and_removes_players() {
+        let store = InMemoryStore::new();
+        let mut alice = record("alice");
+
+        store.save(&alice).unwrap();
+        alice.name = "alice2".to_owned();
+        store.save(&alice).unwrap();
+        assert_eq!(store.load().unwrap(), vec![alice.clone()]);
+
+        store.remove(&alice.id).unwrap();
+        assert!(store.load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn file_store_keeps_games() {
+        let path = std::env::temp_dir().join(format!("games-{}.jsonl", GameId::new_v4()));
+        let game = GameRecord {
+            game_id: GameId::new_v4(),
+            player1_id: PlayerId::new_v4(),
+            player2_id: PlayerId::new_v4(),
+            started_at: 1_600_000_000,
+        };
+
+        FileStore::open(&path).unwrap().save(&game).unwrap();
+
+        let store: FileStore<GameRecord> = FileStore::open(&path).unwrap();
+        assert_eq!(store.load().unwrap(), vec![game.clone()]);
+        store.remove(&game.game_id).unwrap();
+        assert!(store.load().unwrap().is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+//! Topics WebSocket connections subscribe to, so that whatever is published to a topic reaches
+//! only those following it.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::outbox::Outbox;
+use super::protocol::ServerMessage;
+use super::GameId;
+use crate::error::Error;
+
+/// What a connection may follow. Written as `game:<game id>`, `lobby`, `leaderboard` or
+/// `announcements`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub enum Topic {
+    /// Every change to the game, as a spectator sees it.
+    Game(GameId),
+    /// Games starting and ending.
+    Lobby,
+    /// Ratings and leaderboards changing.
+    Leaderboard,
+    /// Announcements from moderators, which every authenticated player follows to begin with.
+    Announcements,
+}
+
+impl fmt::Display for Topic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Topic::Game(game_id) => write!(f, "game:{}", game_id),
+            Topic::Lobby => write!(f, "lobby"),
+            Topic::Leaderboard => write!(f, "leaderboard"),
+            Topic::Announcements => write!(f, "announcements"),
+        }
+    }
+}
+
+impl FromStr for Topic {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "lobby" => Ok(Topic::Lobby),
+            "leaderboard" => Ok(Topic::Leaderboard),
+            "announcements" => Ok(Topic::Announcements),
+            _ => match s.split_once(':') {
+                Some(("game", game_id)) => GameId::parse_str(game_id)
+                    .map(Topic::Game)
+                    .map_err(|_| Error::InvalidArgument(format!("invalid game id: {}", game_id))),
+                _ => Err(Error::InvalidArgument(format!("unknown topic: {}", s))),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Topic {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Error> {
+        s.parse()
+    }
+}
+
+impl From<Topic> for String {
+    fn from(topic: Topic) -> Self {
+        topic.to_string()
+    }
+}
+
+/// Which connections follow which topics.
+#[derive(Default)]
+pub struct Subscriptions {
+    topics: Mutex<HashMap<Topic, Vec<Outbox>>>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts sending the topic to `outbox`, returning whether it was not being sent there
+    /// already.
+    pub fn subscribe(&self, topic: Topic, outbox: Outbox) -> bool {
+        let mut topics = self.topics.lock().unwrap();
+        let subscribers = topics.entry(topic).or_default();
+        if subscribers.iter().any(|s| s.is_same(&outbox)) {
+            return false;
+        }
+
+        subscribers.push(outbox);
+        true
+    }
+
+    /// Stops sending the topic to `outbox`.
+    pub fn unsubscribe(&self, topic: Topic, outbox: &Outbox) {
+        let mut topics = self.topics.lock().unwrap();
+        if let Some(subscribers) = topics.get_mut(&topic) {
+            subscribers.retain(|s| !s.is_same(outbox));
+            if subscribers.is_empty() {
+                topics.remove(&topic);
+            }
+        }
+    }
+
+    /// Stops sending any topic to `outbox`, whose connection has gone.
+    pub fn forget(&self, outbox: &Outbox) {
+        let mut topics = self.topics.lock().unwrap();
+        topics.retain(|_, subscribers| {
+            subscribers.retain(|s| !s.is_same(outbox));
+            !subscribers.is_empty()
+        });
+    }
+
+    /// Sends `message` to everyone subscribed to the topic, dropping connections that have closed.
+    pub fn publish(&self, topic: Topic, message: &ServerMessage) {
+        let mut topics = self.topics.lock().unwrap();
+        if let Some(subscribers) = topics.get_mut(&topic) {
+            subscribers.retain(|s| !s.is_closed());
+            for subscriber in subscribers.iter() {
+                message.push_to(subscriber);
+            }
+            if subscribers.is_empty() {
+                topics.remove(&topic);
+            }
+        }
+    }
+
+    /// Sends `message` to everyone subscribed to the topic, then unsubscribes them, as for a game
+    /// that is over.
+    pub fn close(&self, topic: Topic, message: &ServerMessage) {
+        self.publish(topic, message);
+        self.topics.lock().unwrap().remove(&topic);
+    }
+
+    /// How many connections are subscribed to the topic.
+    pub fn count(&self, topic: Topic) -> usize {
+        self.topics
+            .lock()
+            .unwrap()
+            .get(&topic)
+            .map_or(0, |subscribers| subscribers.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::battlefun::outbox::{OutboxConfig, OutboxMetrics};
+
+    fn outbox() -> Outbox {
+        let config = OutboxConfig {
+            capacity: 10,
+            eviction_deadline: Duration::from_secs(10),
+        };
+
+        Outbox::new(config, Arc::new(OutboxMetrics::default()))
+    }
+
+    #[test]
+    fn parses_and_prints_topics() {
+        let game_id = GameId::new_v4();
+
+        for topic in [
+            Topic::Game(game_id),
+            Topic::Lobby,
+            Topic::Leaderboard,
+            Topic::Announcements,
+        ] {
+            assert_eq!(topic.to_string().parse::<Topic>().unwrap(), topic);
+        }
+        assert_eq!(
+            serde_json::from_str::<Topic>(&format!(r#""game:{}""#, game_id)).unwrap(),
+            Topic::Game(game_id)
+        );
+
+        for invalid in &["", "game", "game:42", "lobbies", "LOBBY"] {
+            assert!(invalid.parse::<Topic>().is_err());
+        }
+    }
+
+    #[test]
+    fn publishes_to_subscribers_until_they_leave() {
+        let subscriptions = Subscriptions::new();
+        let game = Topic::Game(GameId::new_v4());
+        let (viewer, player) = (outbox(), outbox());
+
+        assert!(subscriptions.subscribe(game, viewer.clone()));
+        assert!(!subscriptions.subscribe(game, viewer.clone()));
+        assert!(subscriptions.subscribe(game, player.clone()));
+        assert!(subscriptions.subscribe(Topic::Lobby, player.clone()));
+        assert_eq!(subscriptions.count(game), 2);
+
+        subscriptions.unsubscribe(game, &player);
+        assert_eq!(subscriptions.count(game), 1);
+
+        player.close();
+        subscriptions.publish(Topic::Lobby, &ServerMessage::Pong);
+        assert_eq!(subscriptions.count(Topic::Lobby), 0);
+
+        subscriptions.forget(&viewer);
+        assert_eq!(subscriptions.count(game), 0);
+    }
+}
@@ -0,0 +1,66 @@
+use super::{CellIndex, GameId, PlayerId, RequestId, ShipPlacement};
+use crate::error::Error;
+
+/// Carries game commands to a game engine. Results are not returned from these calls; engines
+/// deliver them asynchronously as `FromGameFn` messages to `BattleFun::handle_statefun_message`,
+/// tagged with the `request_id` of the command they answer.
+pub trait GameEngineTransport: Send + Sync {
+    #[allow(clippy::too_many_arguments)]
+    fn create_game(
+        &self,
+        request_id: RequestId,
+        game_id: GameId,
+        player1_id: PlayerId,
+        player1_ships: ShipPlacement,
+        player2_id: PlayerId,
+        player2_ships: ShipPlacement,
+    ) -> Result<(), Error>;
+
+    fn turn(
+        &self,
+        request_id: RequestId,
+        game_id: GameId,
+        player_id: PlayerId,
+        cell: CellIndex,
+    ) -> Result<(), Error>;
+
+    fn get_game_status(&self, request_id: RequestId, game_id: GameId) -> Result<(), Error>;
+
+    fn resign(
+        &self,
+        request_id: RequestId,
+        game_id: GameId,
+        player_id: PlayerId,
+    ) -> Result<(), Error>;
+}
+
+/// A game engine that cannot be reached, refusing every command.
+#[cfg(test)]
+pub struct UnreachableEngine;
+
+#[cfg(test)]
+impl GameEngineTransport for UnreachableEngine {
+    fn create_game(
+        &self,
+        _: RequestId,
+        _: GameId,
+        _: PlayerId,
+        _: ShipPlacement,
+        _: PlayerId,
+        _: ShipPlacement,
+    ) -> Result<(), Error> {
+        Err(Error::Other)
+    }
+
+    fn turn(&self, _: RequestId, _: GameId, _: PlayerId, _: CellIndex) -> Result<(), Error> {
+        Err(Error::Other)
+    }
+
+    fn get_game_status(&self, _: RequestId, _: GameId) -> Result<(), Error> {
+        Err(Error::Other)
+    }
+
+    fn resign(&self, _: RequestId, _: GameId, _: PlayerId) -> Result<(), Error> {
+        Err(Error::Other)
+    }
+}
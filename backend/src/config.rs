@@ -0,0 +1,125 @@
+use std::env;
+use std::time::Duration;
+
+use crate::battlefun::{
+    clock::ClockConfig,
+    fleet::{FleetSpec, DEFAULT_FLEET},
+    matchmaking::RatingWindow,
+    outbox::OutboxConfig,
+};
+
+pub enum GameEngine {
+    Kafka,
+    InMemory,
+}
+
+pub enum StoreKind {
+    InMemory,
+    File(String),
+}
+
+pub struct Config {
+    pub game_engine: GameEngine,
+    pub player_store: StoreKind,
+    pub game_store: StoreKind,
+    pub archive_store: StoreKind,
+    pub kafka_brokers: String,
+    pub to_statefun_topic: String,
+    pub from_statefun_topic: String,
+    pub kafka_consumer_group: String,
+    pub fleet: FleetSpec,
+    pub turn_result_timeout: Duration,
+    pub outbox: OutboxConfig,
+    pub queue_timeout: Duration,
+    pub rating_window: RatingWindow,
+    pub bot_move_delay: Duration,
+    pub clock: ClockConfig,
+    pub token_secret: Option<String>,
+    pub token_ttl: Duration,
+    pub admin_token: Option<String>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let game_engine = match env_or("GAME_ENGINE", "kafka").as_str() {
+            "memory" => GameEngine::InMemory,
+            "kafka" => GameEngine::Kafka,
+            other => panic!("unknown GAME_ENGINE: {}", other),
+        };
+
+        let fleet = FleetSpec::parse(
+            env_parse_or("BOARD_WIDTH", 10),
+            env_parse_or("BOARD_HEIGHT", 10),
+            &env_or("FLEET", DEFAULT_FLEET),
+        )
+        .unwrap_or_else(|e| panic!("invalid FLEET: {}", e));
+
+        Self {
+            game_engine,
+            player_store: store_kind("PLAYER_STORE", "players.jsonl"),
+            game_store: store_kind("GAME_STORE", "games.jsonl"),
+            archive_store: store_kind("ARCHIVE_STORE", "archive.jsonl"),
+            kafka_brokers: env_or("KAFKA_BROKERS", "kafka-broker:9092"),
+            to_statefun_topic: env_or("KAFKA_TO_STATEFUN_TOPIC", "to-statefun"),
+            from_statefun_topic: env_or("KAFKA_FROM_STATEFUN_TOPIC", "from-statefun"),
+            kafka_consumer_group: env_or("KAFKA_CONSUMER_GROUP", "battlefun-backend"),
+            fleet,
+            turn_result_timeout: Duration::from_millis(env_parse_or(
+                "TURN_RESULT_TIMEOUT_MS",
+                5_000,
+            )),
+            outbox: OutboxConfig {
+                capacity: env_parse_or("OUTBOX_CAPACITY", 64),
+                eviction_deadline: Duration::from_millis(env_parse_or(
+                    "OUTBOX_EVICTION_MS",
+                    10_000,
+                )),
+            },
+            queue_timeout: Duration::from_secs(env_parse_or("QUEUE_TIMEOUT_SECS", 300)),
+            rating_window: RatingWindow {
+                initial: env_parse_or("RATING_WINDOW", 100.0),
+                growth_per_sec: env_parse_or("RATING_WINDOW_GROWTH", 10.0),
+            },
+            bot_move_delay: Duration::from_millis(env_parse_or("BOT_MOVE_DELAY_MS", 1_000)),
+            clock: ClockConfig {
+                turn_limit: time_limit("TURN_TIME_LIMIT_SECS"),
+                game_limit: time_limit("GAME_TIME_LIMIT_SECS"),
+                warning: Duration::from_secs(env_parse_or("CLOCK_WARNING_SECS", 10)),
+            },
+            token_secret: env::var("TOKEN_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty()),
+            token_ttl: Duration::from_secs(env_parse_or("TOKEN_TTL_SECS", 7 * 24 * 60 * 60)),
+            admin_token: env::var("ADMIN_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+        }
+    }
+}
+
+/// Reads where to keep something from `key`, and the file to keep it in from `<key>_PATH`.
+fn store_kind(key: &str, default_path: &str) -> StoreKind {
+    match env_or(key, "memory").as_str() {
+        "memory" => StoreKind::InMemory,
+        "file" => StoreKind::File(env_or(&format!("{}_PATH", key), default_path)),
+        other => panic!("unknown {}: {}", key, other),
+    }
+}
+
+/// Reads a time limit in seconds from `key`, where none or 0 means no limit.
+fn time_limit(key: &str) -> Option<Duration> {
+    Some(Duration::from_secs(env_parse_or(key, 0))).filter(|limit| *limit > Duration::from_secs(0))
+}
+
+fn env_or(key: &str, default: &str) -> String {
+    env::var(key).unwrap_or_else(|_| default.to_owned())
+}
+
+fn env_parse_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("invalid {}: {}", key, value)),
+        Err(_) => default,
+    }
+}
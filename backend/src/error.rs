@@ -1,31 +1,68 @@
-use crate::battlefun::GameId;
+use crate::battlefun::{
+    fleet::PlacementViolation, lobby::ChallengeId, proto::from_game_fn::Failure, CellIndex, GameId,
+    PlayerId,
+};
+use crate::game::FailureCode;
 use serde::Serialize;
 use std::convert::Infallible;
 use thiserror::Error;
-use warp::{http::StatusCode, Rejection, Reply};
+use warp::{
+    http::{header::WWW_AUTHENTICATE, HeaderValue, StatusCode},
+    Rejection, Reply,
+};
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("unknown game: {0}")]
     NoSuchGame(GameId),
 
+    #[error("not queued for a game")]
+    NotQueued,
+
+    #[error("unknown player: {0}")]
+    NoSuchPlayer(PlayerId),
+
+    #[error("unknown lobby: {0}")]
+    NoSuchLobby(String),
+
+    #[error("unknown challenge: {0}")]
+    NoSuchChallenge(ChallengeId),
+
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
     #[error("invalid argument: {0}")]
     InvalidArgument(String),
 
+    #[error("invalid argument: ship placement")]
+    InvalidShipPlacement(Vec<PlacementViolation>),
+
+    #[error("{}", .0.failure_description)]
+    GameEngineFailure(Failure),
+
+    #[error("timed out waiting for the game engine")]
+    GameEngineTimeout,
+
+    #[error("the game engine's reply does not include the shot at cell {0}")]
+    ShotNotRecorded(CellIndex),
+
     #[error("protobuf serialization error: {0}")]
     ProtobufEncodeError(#[from] prost::EncodeError),
 
     #[error("protobuf deserialization error: {0}")]
     ProtobufDecodeError(#[from] prost::DecodeError),
 
+    #[error("store error: {0}")]
+    StoreError(#[from] std::io::Error),
+
     #[error("kafka communication error: {0}")]
     KafkaError(#[from] rdkafka::error::KafkaError),
 
-    #[error("error from statefun: {0}")]
-    ErrorFromStatefun(String),
-
     #[error("unknown error")]
-    #[allow(dead_code)]
     Other,
 }
 
@@ -34,35 +71,80 @@ impl warp::reject::Reject for Error {}
 #[derive(Serialize)]
 struct ErrorResponse {
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<Vec<PlacementViolation>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<FailureCode>,
 }
 
 pub async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply, Infallible> {
     let code;
     let message: String;
+    let mut details = None;
+    let mut failure_code = None;
 
     if err.is_not_found() {
         code = StatusCode::NOT_FOUND;
         message = "Not Found".to_owned();
-    } else if let Some(_) = err.find::<warp::filters::body::BodyDeserializeError>() {
+    } else if err
+        .find::<warp::filters::body::BodyDeserializeError>()
+        .is_some()
+    {
         code = StatusCode::BAD_REQUEST;
         message = "Invalid Body".to_owned();
     } else if let Some(e) = err.find::<Error>() {
         match e {
-            Error::NoSuchGame(_) => {
+            Error::NoSuchGame(_)
+            | Error::NotQueued
+            | Error::NoSuchPlayer(_)
+            | Error::NoSuchLobby(_)
+            | Error::NoSuchChallenge(_) => {
                 code = StatusCode::NOT_FOUND;
                 message = format!("{}", e);
             }
+            Error::Unauthorized(_) => {
+                code = StatusCode::UNAUTHORIZED;
+                message = format!("{}", e);
+            }
+            Error::Forbidden(_) => {
+                code = StatusCode::FORBIDDEN;
+                message = format!("{}", e);
+            }
             Error::InvalidArgument(_) => {
                 code = StatusCode::BAD_REQUEST;
                 message = format!("{}", e);
             }
+            Error::InvalidShipPlacement(violations) => {
+                code = StatusCode::BAD_REQUEST;
+                message = format!("{}", e);
+                details = Some(violations.clone());
+            }
+            Error::GameEngineFailure(failure) => {
+                code = match FailureCode::from_code(failure.code) {
+                    Some(FailureCode::UnknownGame) => StatusCode::NOT_FOUND,
+                    Some(FailureCode::NotDelivered) => StatusCode::BAD_GATEWAY,
+                    _ => StatusCode::CONFLICT,
+                };
+                message = format!("{}", e);
+                failure_code = Some(failure.code);
+            }
+            Error::GameEngineTimeout => {
+                code = StatusCode::GATEWAY_TIMEOUT;
+                message = format!("{}", e);
+            }
+            Error::ShotNotRecorded(_) => {
+                code = StatusCode::CONFLICT;
+                message = format!("{}", e);
+            }
             _ => {
                 eprintln!("unhandled application error: {:?}", err);
                 code = StatusCode::INTERNAL_SERVER_ERROR;
                 message = "Internal Server Error".to_owned();
             }
         }
-    } else if let Some(_) = err.find::<warp::reject::MethodNotAllowed>() {
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
         code = StatusCode::METHOD_NOT_ALLOWED;
         message = "Method Not Allowed".to_owned();
     } else {
@@ -71,7 +153,19 @@ pub async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply,
         message = "Internal Server Error".to_owned();
     }
 
-    let json = warp::reply::json(&ErrorResponse { message });
+    let json = warp::reply::json(&ErrorResponse {
+        message,
+        details,
+        code: failure_code,
+        reason: failure_code.and_then(FailureCode::from_code),
+    });
+
+    let mut response = warp::reply::with_status(json, code).into_response();
+    if code == StatusCode::UNAUTHORIZED {
+        response
+            .headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
 
-    Ok(warp::reply::with_status(json, code))
+    Ok(response)
 }
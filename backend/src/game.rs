@@ -1,122 +1,690 @@
-use crate::PlayerId;
+//! The battlefun rules, mirroring `GameLogic` in the statefun function so games can be evaluated
+//! in-process and the two implementations can be checked against each other.
+
 use serde::Serialize;
-use std::collections::HashMap;
-
-type ShipPlacement = HashMap<String, Vec<u8>>;
-type Shots = Vec<u8>;
-
-#[derive(Serialize, Debug)]
-pub struct GameState {
-    pub current_state: String,
-    pub player1_turn: bool,
-
-    pub player1_id: PlayerId,
-    pub player1_shots: Shots,
-    pub player1_ships: ShipPlacement,
-
-    pub player2_id: PlayerId,
-    pub player2_ships: ShipPlacement,
-    pub player2_shots: Shots,
-}
-
-impl GameState {
-    pub fn new(
-        player1_id: PlayerId,
-        player1_ships: ShipPlacement,
-        player2_id: PlayerId,
-        player2_ships: ShipPlacement,
-    ) -> Self {
-        Self {
-            current_state: "IN_PROGRESS".to_string(),
-            player1_turn: true,
-
-            player1_id: player1_id,
-            player1_shots: Vec::new(),
-            player1_ships: player1_ships,
-
-            player2_id: player2_id,
-            player2_ships: player2_ships,
-            player2_shots: Vec::new(),
+
+use crate::battlefun::proto::{
+    from_game_fn::{Failure, GameUpdate},
+    to_game_fn::{CreateGame, Resign, Turn},
+    GameStatus, ShipPlacement, Shot,
+};
+
+/// Failure codes shared with the statefun function's `FailureCodes`, and one of our own for
+/// commands that never reached it.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FailureCode {
+    GameAlreadyFinished = 1,
+    NotPlayersTurn = 2,
+    ShotWasAlreadyMade = 3,
+    UnknownGame = 5,
+    NotDelivered = -1,
+}
+
+impl FailureCode {
+    pub fn from_code(code: i64) -> Option<Self> {
+        match code {
+            1 => Some(FailureCode::GameAlreadyFinished),
+            2 => Some(FailureCode::NotPlayersTurn),
+            3 => Some(FailureCode::ShotWasAlreadyMade),
+            5 => Some(FailureCode::UnknownGame),
+            -1 => Some(FailureCode::NotDelivered),
+            _ => None,
         }
     }
 
-    pub fn get_player_game_state(
-        &self,
-        player_id: PlayerId,
-    ) -> Result<PlayerGameState, &'static str> {
-        if self.player1_id == player_id {
-            let player_game_state = PlayerGameState {
-                opponent_id: self.player2_id,
-                current_state: self.current_state.clone(),
-                your_turn: self.player1_turn,
-                your_shots: self.player1_shots.clone(),
-                opponent_shots: self.player2_shots.clone(),
-                destroyed_opponent_ships: get_destroyed_ships(
-                    &self.player2_ships,
-                    &self.player1_shots,
-                ),
-                your_ships: self.player1_ships.clone(),
-            };
-
-            return Ok(player_game_state);
+    pub fn failure(self, description: &str) -> Failure {
+        Failure {
+            code: self as i64,
+            failure_description: description.to_owned(),
         }
+    }
+}
 
-        if self.player2_id == player_id {
-            let player_game_state = PlayerGameState {
-                opponent_id: self.player1_id,
-                current_state: self.current_state.clone(),
-                your_turn: !self.player1_turn,
-                your_shots: self.player2_shots.clone(),
-                opponent_shots: self.player1_shots.clone(),
-                destroyed_opponent_ships: get_destroyed_ships(
-                    &self.player1_ships,
-                    &self.player2_shots,
-                ),
-                your_ships: self.player2_ships.clone(),
-            };
-
-            return Ok(player_game_state);
-        }
+/// What a single shot did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShotOutcome {
+    pub hit: bool,
+    /// The ship the shot sank, if it hit the last intact cell of one.
+    pub sunk: Option<String>,
+}
+
+/// A shot taken in a game, and what it did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub player_id: String,
+    pub cell: i64,
+    pub outcome: ShotOutcome,
+}
 
-        Err("Invalid player ID")
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Player1,
+    Player2,
+}
+
+pub fn create(create_game: &CreateGame) -> GameUpdate {
+    GameUpdate {
+        game_id: create_game.game_id.clone(),
+        player1_id: create_game.player1_id.clone(),
+        player2_id: create_game.player2_id.clone(),
+        player1_placement: create_game.player1_placement.clone(),
+        player2_placement: create_game.player2_placement.clone(),
+        status: GameStatus::Player1Turn as i32,
+        player1_shots: Vec::new(),
+        player2_shots: Vec::new(),
+    }
+}
+
+/// Applies `turn` to `current`, returning the next state of the game. As in statefun, any player
+/// that is not player 1 is taken to be player 2.
+pub fn apply(current: &GameUpdate, turn: &Turn) -> Result<GameUpdate, Failure> {
+    let side = side_of(current, &turn.player_id);
+    let cell = turn.shot;
+
+    if is_game_over(current) {
+        return Err(FailureCode::GameAlreadyFinished.failure("The game is already finished"));
+    }
+    if !is_players_turn(current, side) {
+        return Err(FailureCode::NotPlayersTurn
+            .failure(&format!("It is not the turn of player {}", turn.player_id)));
+    }
+    if shots_of(current, side)
+        .iter()
+        .any(|shot| shot.cell_id == cell)
+    {
+        return Err(FailureCode::ShotWasAlreadyMade.failure("The shot was already made"));
     }
 
-    pub fn register_shot(&mut self, player_id: PlayerId, cell: u8) -> Result<(), &'static str> {
-        if self.player1_id == player_id {
-            self.player1_shots.push(cell);
-            return Ok(());
+    let mut next = current.clone();
+    let opponent_placement = opponent_placement_of(current, side);
+    let shot = Shot {
+        cell_id: cell,
+        hit: did_shot_hit(opponent_placement, cell),
+    };
+
+    let shots = match side {
+        Side::Player1 => &mut next.player1_shots,
+        Side::Player2 => &mut next.player2_shots,
+    };
+    shots.push(shot);
+
+    next.status = if has_remaining_ships(opponent_placement, shots) {
+        match side {
+            Side::Player1 => GameStatus::Player2Turn,
+            Side::Player2 => GameStatus::Player1Turn,
         }
+    } else {
+        match side {
+            Side::Player1 => GameStatus::Player1Win,
+            Side::Player2 => GameStatus::Player2Win,
+        }
+    } as i32;
+
+    Ok(next)
+}
+
+/// Ends the game in favour of the opponent of the resigning player.
+pub fn resign(current: &GameUpdate, resign: &Resign) -> Result<GameUpdate, Failure> {
+    if is_game_over(current) {
+        return Err(FailureCode::GameAlreadyFinished.failure("The game is already finished"));
+    }
+
+    let mut next = current.clone();
+
+    next.status = match side_of(current, &resign.player_id) {
+        Side::Player1 => GameStatus::Player2Win,
+        Side::Player2 => GameStatus::Player1Win,
+    } as i32;
+
+    Ok(next)
+}
+
+/// The id of the player who won the game, if it is over.
+pub fn winner_id(game: &GameUpdate) -> Option<&str> {
+    match GameStatus::from_i32(game.status) {
+        Some(GameStatus::Player1Win) => Some(&game.player1_id),
+        Some(GameStatus::Player2Win) => Some(&game.player2_id),
+        _ => None,
+    }
+}
+
+pub fn is_game_over(game: &GameUpdate) -> bool {
+    let status = GameStatus::from_i32(game.status);
+    status == Some(GameStatus::Player1Win) || status == Some(GameStatus::Player2Win)
+}
+
+/// The ships of `placement` that have had every one of their cells shot.
+pub fn destroyed_ships(placement: &ShipPlacement, shots: &[Shot]) -> Vec<String> {
+    placement
+        .ships
+        .iter()
+        .filter(|ship| {
+            ship.cells
+                .iter()
+                .all(|cell| shots.iter().any(|shot| shot.cell_id == *cell))
+        })
+        .map(|ship| ship.r#type.clone())
+        .collect()
+}
+
+/// The outcome of the shot `player_id` made at `cell`, if `game` contains it.
+pub fn shot_outcome(game: &GameUpdate, player_id: &str, cell: i64) -> Option<ShotOutcome> {
+    let side = side_of(game, player_id);
+    let shots = shots_of(game, side);
+    let position = shots.iter().position(|shot| shot.cell_id == cell)?;
+    let (shot, shots_so_far) = (&shots[position], &shots[..=position]);
+
+    // Only the shots up to this one count, so a ship sunk later is not reported sunk here.
+    let sunk = opponent_placement_of(game, side)
+        .and_then(|placement| {
+            placement.ships.iter().find(|ship| {
+                ship.cells.contains(&cell)
+                    && ship
+                        .cells
+                        .iter()
+                        .all(|cell| shots_so_far.iter().any(|shot| shot.cell_id == *cell))
+            })
+        })
+        .map(|ship| ship.r#type.clone());
+
+    Some(ShotOutcome {
+        hit: shot.hit,
+        sunk,
+    })
+}
+
+/// The shots of `game` in the order they were taken, found by playing each player's shots again
+/// from the start in turn. Stops at the first shot the rules would not have allowed.
+pub fn moves(game: &GameUpdate) -> Vec<Move> {
+    let mut current = GameUpdate {
+        status: GameStatus::Player1Turn as i32,
+        player1_shots: Vec::new(),
+        player2_shots: Vec::new(),
+        ..game.clone()
+    };
+    let mut player1_shots = game.player1_shots.iter();
+    let mut player2_shots = game.player2_shots.iter();
+    let mut moves = Vec::new();
 
-        if self.player2_id == player_id {
-            self.player2_shots.push(cell);
-            return Ok(());
+    loop {
+        let (player_id, shot) = match GameStatus::from_i32(current.status) {
+            Some(GameStatus::Player1Turn) => (&game.player1_id, player1_shots.next()),
+            Some(GameStatus::Player2Turn) => (&game.player2_id, player2_shots.next()),
+            _ => break,
+        };
+        let cell = match shot {
+            Some(shot) => shot.cell_id,
+            None => break,
+        };
+
+        let turn = Turn {
+            game_id: game.game_id.clone(),
+            player_id: player_id.clone(),
+            shot: cell,
+        };
+        current = match apply(&current, &turn) {
+            Ok(next) => next,
+            Err(_) => break,
+        };
+
+        if let Some(outcome) = shot_outcome(&current, player_id, cell) {
+            moves.push(Move {
+                player_id: player_id.clone(),
+                cell,
+                outcome,
+            });
         }
+    }
 
-        Err("Invalid player ID")
+    moves
+}
+
+pub fn is_turn_of(game: &GameUpdate, player_id: &str) -> bool {
+    is_players_turn(game, side_of(game, player_id))
+}
+
+fn side_of(game: &GameUpdate, player_id: &str) -> Side {
+    if game.player1_id == player_id {
+        Side::Player1
+    } else {
+        Side::Player2
     }
 }
 
-#[derive(Serialize, Debug)]
-pub struct PlayerGameState {
-    pub opponent_id: PlayerId,
-    pub current_state: String,
-    pub your_turn: bool,
-    pub your_shots: Shots,
-    pub opponent_shots: Shots,
-    pub destroyed_opponent_ships: Vec<String>,
-    pub your_ships: ShipPlacement,
+fn is_players_turn(game: &GameUpdate, side: Side) -> bool {
+    match GameStatus::from_i32(game.status) {
+        Some(GameStatus::Player1Turn) => side == Side::Player1,
+        Some(GameStatus::Player2Turn) => side == Side::Player2,
+        _ => false,
+    }
+}
+
+fn shots_of(game: &GameUpdate, side: Side) -> &[Shot] {
+    match side {
+        Side::Player1 => &game.player1_shots,
+        Side::Player2 => &game.player2_shots,
+    }
 }
 
-fn get_destroyed_ships(ship_placement: &ShipPlacement, shots: &Shots) -> Vec<String> {
-    let mut destroyed_ships = Vec::new();
+fn opponent_placement_of(game: &GameUpdate, side: Side) -> Option<&ShipPlacement> {
+    match side {
+        Side::Player1 => game.player2_placement.as_ref(),
+        Side::Player2 => game.player1_placement.as_ref(),
+    }
+}
+
+fn did_shot_hit(placement: Option<&ShipPlacement>, cell: i64) -> bool {
+    placement
+        .iter()
+        .flat_map(|placement| placement.ships.iter())
+        .any(|ship| ship.cells.contains(&cell))
+}
+
+fn has_remaining_ships(placement: Option<&ShipPlacement>, shots: &[Shot]) -> bool {
+    placement
+        .iter()
+        .flat_map(|placement| placement.ships.iter())
+        .flat_map(|ship| ship.cells.iter())
+        .any(|cell| !shots.iter().any(|shot| shot.cell_id == *cell))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battlefun::proto::Ship;
+
+    const PLAYER1: &str = "player-1";
+    const PLAYER2: &str = "player-2";
+
+    fn ships(positions: &[&[i64]]) -> ShipPlacement {
+        let ships = positions
+            .iter()
+            .zip(["a", "b", "c"].iter())
+            .map(|(cells, r#type)| Ship {
+                r#type: (*r#type).to_owned(),
+                cells: cells.to_vec(),
+            })
+            .collect();
+
+        ShipPlacement { ships }
+    }
+
+    fn default_ships() -> ShipPlacement {
+        ships(&[&[1, 2, 3], &[4, 5, 6, 7], &[8, 9, 10, 11]])
+    }
 
-    for (ship, placement) in ship_placement.iter() {
-        let is_destroyed = placement.iter().map(|cell| shots.contains(cell)).all(|b| b);
+    fn game(player1_placement: ShipPlacement, player2_placement: ShipPlacement) -> GameUpdate {
+        create(&CreateGame {
+            game_id: "game-1".to_owned(),
+            player1_id: PLAYER1.to_owned(),
+            player2_id: PLAYER2.to_owned(),
+            player1_placement: Some(player1_placement),
+            player2_placement: Some(player2_placement),
+        })
+    }
 
-        if is_destroyed {
-            destroyed_ships.push(ship.clone());
+    fn turn(player_id: &str, shot: i64) -> Turn {
+        Turn {
+            game_id: "game-1".to_owned(),
+            player_id: player_id.to_owned(),
+            shot,
         }
     }
 
-    destroyed_ships
+    fn apply_turn(game: GameUpdate, player_id: &str, shot: i64) -> GameUpdate {
+        apply(&game, &turn(player_id, shot)).expect("turn should be accepted")
+    }
+
+    fn failure_code(game: &GameUpdate, player_id: &str, shot: i64) -> Option<FailureCode> {
+        let failure = apply(game, &turn(player_id, shot)).expect_err("turn should be rejected");
+        FailureCode::from_code(failure.code)
+    }
+
+    fn status(game: &GameUpdate) -> Option<GameStatus> {
+        GameStatus::from_i32(game.status)
+    }
+
+    #[test]
+    fn create_game() {
+        let created = game(default_ships(), default_ships());
+
+        assert_eq!(created.game_id, "game-1");
+        assert_eq!(created.player1_id, PLAYER1);
+        assert_eq!(created.player2_id, PLAYER2);
+        assert_eq!(created.player1_placement, Some(default_ships()));
+        assert_eq!(created.player2_placement, Some(default_ships()));
+        assert!(created.player1_shots.is_empty());
+        assert!(created.player2_shots.is_empty());
+    }
+
+    #[test]
+    fn first_turn_goes_to_player1() {
+        let game = game(default_ships(), default_ships());
+
+        assert_eq!(status(&game), Some(GameStatus::Player1Turn));
+    }
+
+    #[test]
+    fn alternate_turns() {
+        let mut game = game(default_ships(), default_ships());
+
+        for i in 0..10 {
+            game = apply_turn(game, PLAYER1, 13 + i);
+            assert_eq!(status(&game), Some(GameStatus::Player2Turn));
+            game = apply_turn(game, PLAYER2, 13 + i);
+            assert_eq!(status(&game), Some(GameStatus::Player1Turn));
+        }
+    }
+
+    #[test]
+    fn hits_do_not_grant_another_turn() {
+        let game = apply_turn(game(default_ships(), default_ships()), PLAYER1, 1);
+
+        assert_eq!(status(&game), Some(GameStatus::Player2Turn));
+    }
+
+    #[test]
+    fn shot_is_kept_in_history_in_order() {
+        let mut game = game(default_ships(), default_ships());
+
+        game = apply_turn(game, PLAYER1, 50);
+        game = apply_turn(game, PLAYER2, 60);
+        game = apply_turn(game, PLAYER1, 2);
+
+        let player1_cells: Vec<i64> = game.player1_shots.iter().map(|s| s.cell_id).collect();
+        let player2_cells: Vec<i64> = game.player2_shots.iter().map(|s| s.cell_id).collect();
+        assert_eq!(player1_cells, vec![50, 2]);
+        assert_eq!(player2_cells, vec![60]);
+    }
+
+    #[test]
+    fn shots_record_hits_and_misses() {
+        let mut game = game(ships(&[&[20]]), ships(&[&[30, 31]]));
+
+        game = apply_turn(game, PLAYER1, 30);
+        game = apply_turn(game, PLAYER2, 21);
+
+        assert_eq!(
+            game.player1_shots,
+            vec![Shot {
+                cell_id: 30,
+                hit: true
+            }]
+        );
+        assert_eq!(
+            game.player2_shots,
+            vec![Shot {
+                cell_id: 21,
+                hit: false
+            }]
+        );
+    }
+
+    #[test]
+    fn replays_moves_in_turn_order() {
+        let mut game = game(ships(&[&[20]]), ships(&[&[30, 31]]));
+
+        game = apply_turn(game, PLAYER1, 30);
+        game = apply_turn(game, PLAYER2, 21);
+        game = apply_turn(game, PLAYER1, 31);
+
+        let played = |player_id: &str, cell, hit, sunk: Option<&str>| Move {
+            player_id: player_id.to_owned(),
+            cell,
+            outcome: ShotOutcome {
+                hit,
+                sunk: sunk.map(str::to_owned),
+            },
+        };
+        assert_eq!(
+            moves(&game),
+            vec![
+                played(PLAYER1, 30, true, None),
+                played(PLAYER2, 21, false, None),
+                played(PLAYER1, 31, true, Some("a")),
+            ]
+        );
+    }
+
+    #[test]
+    fn player1_wins() {
+        let mut game = game(default_ships(), default_ships());
+
+        for i in 1..=10 {
+            game = apply_turn(game, PLAYER1, i);
+            game = apply_turn(game, PLAYER2, 13 + i);
+        }
+        game = apply_turn(game, PLAYER1, 11);
+
+        assert_eq!(status(&game), Some(GameStatus::Player1Win));
+    }
+
+    #[test]
+    fn player2_wins() {
+        let mut game = game(default_ships(), default_ships());
+
+        for i in 1..=10 {
+            game = apply_turn(game, PLAYER1, 10_000 + i);
+            game = apply_turn(game, PLAYER2, i);
+        }
+        game = apply_turn(game, PLAYER1, 50_000);
+        game = apply_turn(game, PLAYER2, 11);
+
+        assert_eq!(status(&game), Some(GameStatus::Player2Win));
+    }
+
+    #[test]
+    fn shots_on_other_players_cells_do_not_count_towards_win() {
+        let mut game = game(ships(&[&[1]]), ships(&[&[2]]));
+
+        game = apply_turn(game, PLAYER1, 1);
+
+        assert_eq!(status(&game), Some(GameStatus::Player2Turn));
+    }
+
+    #[test]
+    fn player2_cannot_go_first() {
+        let game = game(default_ships(), default_ships());
+
+        assert_eq!(
+            failure_code(&game, PLAYER2, 1),
+            Some(FailureCode::NotPlayersTurn)
+        );
+    }
+
+    #[test]
+    fn player_cannot_shoot_twice_in_a_row() {
+        let game = apply_turn(game(default_ships(), default_ships()), PLAYER1, 1);
+
+        assert_eq!(
+            failure_code(&game, PLAYER1, 2),
+            Some(FailureCode::NotPlayersTurn)
+        );
+    }
+
+    #[test]
+    fn unknown_players_are_treated_as_player2() {
+        let game = game(default_ships(), default_ships());
+
+        assert_eq!(
+            failure_code(&game, "someone-else", 1),
+            Some(FailureCode::NotPlayersTurn)
+        );
+
+        let game = apply_turn(game, PLAYER1, 50);
+        let game = apply_turn(game, "someone-else", 1);
+        assert_eq!(game.player2_shots.len(), 1);
+    }
+
+    #[test]
+    fn repeated_shot_is_rejected() {
+        let mut game = game(default_ships(), default_ships());
+
+        game = apply_turn(game, PLAYER1, 42);
+        game = apply_turn(game, PLAYER2, 43);
+
+        assert_eq!(
+            failure_code(&game, PLAYER1, 42),
+            Some(FailureCode::ShotWasAlreadyMade)
+        );
+    }
+
+    #[test]
+    fn rejected_turn_leaves_game_untouched() {
+        let game = apply_turn(game(default_ships(), default_ships()), PLAYER1, 42);
+        let before = game.clone();
+
+        let _ = apply(&game, &turn(PLAYER1, 43));
+
+        assert_eq!(game, before);
+    }
+
+    #[test]
+    fn players_may_shoot_the_same_cell_as_each_other() {
+        let game = apply_turn(game(default_ships(), default_ships()), PLAYER1, 42);
+
+        let game = apply_turn(game, PLAYER2, 42);
+
+        assert_eq!(status(&game), Some(GameStatus::Player1Turn));
+    }
+
+    #[test]
+    fn finished_game_rejects_turns() {
+        let mut game = game(ships(&[&[1]]), ships(&[&[2]]));
+
+        game = apply_turn(game, PLAYER1, 2);
+
+        assert_eq!(status(&game), Some(GameStatus::Player1Win));
+        assert_eq!(
+            failure_code(&game, PLAYER2, 1),
+            Some(FailureCode::GameAlreadyFinished)
+        );
+        assert_eq!(
+            failure_code(&game, PLAYER1, 3),
+            Some(FailureCode::GameAlreadyFinished)
+        );
+    }
+
+    #[test]
+    fn game_over_takes_precedence_over_repeated_shot() {
+        let game = apply_turn(game(ships(&[&[1]]), ships(&[&[2]])), PLAYER1, 2);
+
+        assert_eq!(
+            failure_code(&game, PLAYER1, 2),
+            Some(FailureCode::GameAlreadyFinished)
+        );
+    }
+
+    #[test]
+    fn unknown_status_rejects_turns() {
+        let mut game = game(default_ships(), default_ships());
+        game.status = GameStatus::Unknown as i32;
+
+        assert_eq!(
+            failure_code(&game, PLAYER1, 1),
+            Some(FailureCode::NotPlayersTurn)
+        );
+    }
+
+    fn resignation(player_id: &str) -> Resign {
+        Resign {
+            game_id: "game-1".to_owned(),
+            player_id: player_id.to_owned(),
+        }
+    }
+
+    #[test]
+    fn player1_resigns() {
+        let game = game(default_ships(), default_ships());
+
+        let resigned = resign(&game, &resignation(PLAYER1)).unwrap();
+
+        assert_eq!(status(&resigned), Some(GameStatus::Player2Win));
+        assert_eq!(winner_id(&resigned), Some(PLAYER2));
+        assert!(is_game_over(&resigned));
+    }
+
+    #[test]
+    fn player2_resigns() {
+        let game = game(default_ships(), default_ships());
+
+        let resigned = resign(&game, &resignation(PLAYER2)).unwrap();
+
+        assert_eq!(status(&resigned), Some(GameStatus::Player1Win));
+        assert_eq!(winner_id(&resigned), Some(PLAYER1));
+    }
+
+    #[test]
+    fn finished_game_cannot_be_resigned() {
+        let game = apply_turn(game(ships(&[&[1]]), ships(&[&[2]])), PLAYER1, 2);
+
+        let failure = resign(&game, &resignation(PLAYER1)).unwrap_err();
+
+        assert_eq!(
+            FailureCode::from_code(failure.code),
+            Some(FailureCode::GameAlreadyFinished)
+        );
+        assert_eq!(winner_id(&game), Some(PLAYER1));
+    }
+
+    #[test]
+    fn destroyed_ships_are_fully_hit_ships() {
+        let placement = default_ships();
+        let shots: Vec<Shot> = [1, 2, 3, 4, 5, 50]
+            .iter()
+            .map(|&cell_id| Shot { cell_id, hit: true })
+            .collect();
+
+        assert_eq!(destroyed_ships(&placement, &shots), vec!["a".to_owned()]);
+        assert!(destroyed_ships(&placement, &[]).is_empty());
+    }
+
+    #[test]
+    fn shot_outcomes_report_hits_and_sunk_ships() {
+        let mut game = game(default_ships(), default_ships());
+        game = apply_turn(game, PLAYER1, 1);
+        game = apply_turn(game, PLAYER2, 50);
+        game = apply_turn(game, PLAYER1, 2);
+        game = apply_turn(game, PLAYER2, 51);
+        game = apply_turn(game, PLAYER1, 3);
+
+        let hit = |sunk: Option<&str>| {
+            Some(ShotOutcome {
+                hit: true,
+                sunk: sunk.map(str::to_owned),
+            })
+        };
+        assert_eq!(shot_outcome(&game, PLAYER1, 2), hit(None));
+        assert_eq!(shot_outcome(&game, PLAYER1, 3), hit(Some("a")));
+        assert_eq!(
+            shot_outcome(&game, PLAYER2, 50),
+            Some(ShotOutcome {
+                hit: false,
+                sunk: None
+            })
+        );
+        assert_eq!(shot_outcome(&game, PLAYER2, 1), None);
+        assert!(is_turn_of(&game, PLAYER2));
+        assert!(!is_turn_of(&game, PLAYER1));
+    }
+
+    #[test]
+    fn failure_codes_match_statefun() {
+        for &code in &[
+            FailureCode::GameAlreadyFinished,
+            FailureCode::NotPlayersTurn,
+            FailureCode::ShotWasAlreadyMade,
+            FailureCode::UnknownGame,
+            FailureCode::NotDelivered,
+        ] {
+            assert_eq!(FailureCode::from_code(code as i64), Some(code));
+        }
+        assert_eq!(
+            FailureCode::from_code(1),
+            Some(FailureCode::GameAlreadyFinished)
+        );
+        assert_eq!(FailureCode::from_code(5), Some(FailureCode::UnknownGame));
+        assert_eq!(FailureCode::from_code(4), None);
+    }
 }
@@ -1,16 +1,32 @@
-use bytes::Bytes;
-use prost::Message;
 use serde::{Deserialize, Serialize};
+use tokio::time;
 use warp::{reject, reply::json, Reply};
 
-use crate::{battlefun, error::Error, BattleFunInstance, Result};
+use crate::{battlefun, error::Error, game, BattleFunInstance, Result};
 use battlefun::{
-    proto::from_game_fn, proto::FromGameFn, CellIndex, GameId, PlayerId, ShipPlacement,
+    bot::Difficulty,
+    proto::from_game_fn::{GameUpdate, Response},
+    stats::Period,
+    CellIndex, GameId, PlayerId, ShipPlacement,
 };
 
 #[derive(Deserialize, Debug)]
 pub struct NewGameRequest {
     ships: ShipPlacement,
+    /// Play a bot of this difficulty if no opponent is found before the queue timeout.
+    #[serde(default)]
+    bot_fallback: Option<Difficulty>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BotGameRequest {
+    ships: ShipPlacement,
+    difficulty: Difficulty,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BotGameResponse {
+    game_id: GameId,
 }
 
 #[derive(Serialize, Debug)]
@@ -24,24 +40,91 @@ impl GenericResponse {
     }
 }
 
+/// The most places of a leaderboard that can be asked for at once.
+const MAX_LEADERBOARD_LIMIT: usize = 100;
+
+#[derive(Deserialize, Debug)]
+pub struct LeaderboardQuery {
+    #[serde(default = "default_leaderboard_limit")]
+    limit: usize,
+}
+
+fn default_leaderboard_limit() -> usize {
+    10
+}
+
 #[derive(Deserialize, Debug)]
 pub struct TurnRequest {
     cell: CellIndex,
+    /// Wait for the game engine to evaluate the shot and respond with a `TurnResult`.
+    #[serde(default)]
+    wait: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TurnResult {
+    game_id: GameId,
+    cell: CellIndex,
+    hit: bool,
+    sunk_ship: Option<String>,
+    current_state: i32,
+    your_turn: bool,
+    winner_id: Option<PlayerId>,
+}
+
+impl TurnResult {
+    fn new(
+        game_id: GameId,
+        player_id: PlayerId,
+        cell: CellIndex,
+        game_update: &GameUpdate,
+    ) -> Option<Self> {
+        let player_id = player_id.to_string();
+        let outcome = game::shot_outcome(game_update, &player_id, cell as i64)?;
+
+        Some(Self {
+            game_id,
+            cell,
+            hit: outcome.hit,
+            sunk_ship: outcome.sunk,
+            current_state: game_update.status,
+            your_turn: game::is_turn_of(game_update, &player_id),
+            winner_id: game::winner_id(game_update).and_then(|id| PlayerId::parse_str(id).ok()),
+        })
+    }
 }
 
 pub async fn new_game_handler(
     request: NewGameRequest,
-    token: PlayerId,
+    player_id: PlayerId,
     battlefun_instance: BattleFunInstance,
 ) -> Result<impl Reply> {
-    let mut battlefun = battlefun_instance.write().await;
+    battlefun_instance
+        .queue_for_game(player_id, request.ships, request.bot_fallback)
+        .map_err(reject::custom)?;
 
-    let player_id = match battlefun.player_tokens.get(&token) {
-        Some(id) => *id,
-        None => return Err(reject::not_found()),
-    };
+    Ok(json(&GenericResponse::success()))
+}
+
+pub async fn bot_game_handler(
+    request: BotGameRequest,
+    player_id: PlayerId,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    let game_id = battlefun_instance
+        .play_bot(player_id, request.ships, request.difficulty)
+        .map_err(reject::custom)?;
+
+    Ok(json(&BotGameResponse { game_id }))
+}
 
-    battlefun.matchmaker.play(player_id, request.ships).await;
+pub async fn leave_queue_handler(
+    player_id: PlayerId,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    battlefun_instance
+        .leave_queue(&player_id)
+        .map_err(reject::custom)?;
 
     Ok(json(&GenericResponse::success()))
 }
@@ -49,56 +132,93 @@ pub async fn new_game_handler(
 pub async fn turn_handler(
     game_id: GameId,
     request: TurnRequest,
-    token: PlayerId,
+    player_id: PlayerId,
     battlefun_instance: BattleFunInstance,
 ) -> Result<impl Reply> {
-    let battlefun = battlefun_instance.write().await;
+    let reply = battlefun_instance
+        .take_shot(player_id, game_id, request.cell)
+        .map_err(reject::custom)?;
+
+    if !request.wait {
+        return Ok(json(&GenericResponse::success()));
+    }
 
-    let player_id = match battlefun.player_tokens.get(&token) {
-        Some(id) => *id,
-        None => return Err(reject::not_found()),
+    let response = match time::timeout(battlefun_instance.turn_result_timeout, reply).await {
+        Ok(Ok(response)) => response,
+        _ => return Err(reject::custom(Error::GameEngineTimeout)),
     };
 
-    battlefun
+    match response {
+        Response::GameUpdate(game_update) => {
+            match TurnResult::new(game_id, player_id, request.cell, &game_update) {
+                Some(result) => Ok(json(&result)),
+                None => Err(reject::custom(Error::ShotNotRecorded(request.cell))),
+            }
+        }
+        Response::Failure(failure) => Err(reject::custom(Error::GameEngineFailure(failure))),
+    }
+}
+
+pub async fn resign_handler(
+    game_id: GameId,
+    player_id: PlayerId,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    battlefun_instance
         .gamemaster
-        .write()
-        .await
-        .turn(game_id, player_id, request.cell)
-        .await
+        .resign(game_id, player_id)
         .map_err(reject::custom)?;
 
     Ok(json(&GenericResponse::success()))
 }
 
-pub async fn incoming_kafka_message_handler(
-    body: Bytes,
+/// Lists the finished games of the player, latest first.
+pub async fn player_games_handler(
+    player_id: PlayerId,
     battlefun_instance: BattleFunInstance,
 ) -> Result<impl Reply> {
-    let message = match FromGameFn::decode(body) {
-        Ok(m) => m,
-        Err(e) => return Err(reject::custom(Error::ProtobufDecodeError(e))),
-    };
+    let games = battlefun_instance.archive.games_of(&player_id);
+    if games.is_empty() && !battlefun_instance.players.contains_key(&player_id) {
+        return Err(reject::custom(Error::NoSuchPlayer(player_id)));
+    }
 
-    let game_id = GameId::parse_str(&message.game_id).unwrap();
-    let game_update = match message.response.unwrap() {
-        from_game_fn::Response::GameUpdate(game_update) => game_update,
-        from_game_fn::Response::Failure(failure) => {
-            eprintln!("Got failure: {:?}", failure);
-            return Err(reject::custom(Error::ErrorFromStatefun("uh-oh".to_owned())));
-        }
-    };
+    Ok(json(&games))
+}
 
-    let battlefun = battlefun_instance.write().await;
-    battlefun.handle_game_update(
-        game_id,
-        PlayerId::parse_str(&game_update.player1_id).unwrap(),
-        game_update.player1_placement.unwrap(),
-        PlayerId::parse_str(&game_update.player2_id).unwrap(),
-        game_update.player2_placement.unwrap(),
-        game_update.status,
-        game_update.player1_shots,
-        game_update.player2_shots,
-    );
+/// Returns a finished game with its moves in order. Games still being played have no replay, as
+/// it would give away where the ships are.
+pub async fn replay_handler(
+    game_id: GameId,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    match battlefun_instance.archive.get(&game_id) {
+        Some(game) => Ok(json(&game)),
+        None => Err(reject::custom(Error::NoSuchGame(game_id))),
+    }
+}
 
-    Ok(json(&GenericResponse::success()))
+pub async fn player_stats_handler(
+    player_id: PlayerId,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    let stats = battlefun_instance
+        .player_stats(&player_id)
+        .map_err(reject::custom)?;
+
+    Ok(json(&stats))
+}
+
+pub async fn leaderboard_handler(
+    period: Period,
+    query: LeaderboardQuery,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    if query.limit > MAX_LEADERBOARD_LIMIT {
+        return Err(reject::custom(Error::InvalidArgument(format!(
+            "limit may be at most {}",
+            MAX_LEADERBOARD_LIMIT
+        ))));
+    }
+
+    Ok(json(&battlefun_instance.leaderboard(period, query.limit)))
 }
@@ -1,56 +1,59 @@
-use crate::{battlefun, ws, BattleFunInstance, Result};
+use crate::{battlefun, error::Error, ws, BattleFunInstance, Result};
 use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
 use uuid::Uuid;
-use warp::{http::StatusCode, reject, reply::json, ws::Message, Reply};
+use warp::{http::StatusCode, reject, reply::json, Reply};
 
-use battlefun::{Player, PlayerConnection, PlayerId, PlayerToken};
+use battlefun::{
+    admin::Role, outbox::OutboxMetricsSnapshot, rating::Rating, session::Claims, GameId, Player,
+    PlayerConnection, PlayerId, PlayerToken,
+};
 
 #[derive(Deserialize, Debug)]
 pub struct RegisterRequest {
     name: Option<String>,
-    token: Option<Uuid>,
+    token: Option<PlayerToken>,
 }
 
 #[derive(Serialize, Debug)]
 pub struct RegisterResponse {
     player_id: Uuid,
     name: String,
-    token: Uuid,
+    token: PlayerToken,
+    /// When the token expires, in seconds since the Unix epoch.
+    expires_at: u64,
+    rating: f64,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct TestMessage {
-    message: String,
+#[derive(Serialize, Debug)]
+pub struct TokenResponse {
+    token: PlayerToken,
+    expires_at: u64,
 }
 
-pub async fn publish_handler(
-    body: TestMessage,
-    battlefun_instance: BattleFunInstance,
-) -> Result<impl Reply> {
-    battlefun_instance
-        .read()
-        .await
-        .players
-        .iter()
-        .filter(|(_, player)| player.connection.authenticated)
-        .for_each(|(_, player)| {
-            if let Some(sender) = &player.connection.sender {
-                let _ = sender.send(Ok(Message::text(body.message.clone())));
-            }
-        });
-
-    Ok(StatusCode::OK)
+#[derive(Serialize, Debug)]
+pub struct MetricsResponse {
+    players: usize,
+    games: usize,
+    queued: usize,
+    outbox: OutboxMetricsSnapshot,
 }
 
 pub async fn register_handler(
     body: RegisterRequest,
     battlefun_instance: BattleFunInstance,
 ) -> Result<impl Reply> {
-    let player = register_client(body, battlefun_instance).await?;
+    let player = register_client(body, battlefun_instance.clone()).await?;
+    let (token, claims) = battlefun_instance
+        .issue_token(&player.id)
+        .map_err(reject::custom)?;
+
     Ok(json(&RegisterResponse {
         player_id: player.id,
         name: player.name,
-        token: player.token,
+        token,
+        expires_at: claims.exp,
+        rating: player.rating.value,
     }))
 }
 
@@ -58,21 +61,18 @@ async fn register_client(
     request: RegisterRequest,
     battlefun_instance: BattleFunInstance,
 ) -> Result<Player> {
-    let mut battlefun = battlefun_instance.write().await;
-
-    let player_id: PlayerId = match request.token {
-        Some(t) => match battlefun.player_tokens.get(&t) {
-            Some(id) => *id,
-            None => return Err(reject::not_found()),
-        },
-        None => PlayerId::new_v4(),
-    };
-
-    let player_token: PlayerToken = match request.token {
-        Some(t) => t,
-        None => PlayerToken::new_v4(),
-    };
+    if let Some(token) = request.token {
+        let player_id = battlefun_instance
+            .authenticate(&token)
+            .map_err(reject::custom)?
+            .sub;
+
+        return battlefun_instance
+            .reregister(&player_id, request.name)
+            .map_err(reject::custom);
+    }
 
+    let player_id = PlayerId::new_v4();
     let name = match request.name {
         Some(n) => n,
         None => generate_name(player_id),
@@ -80,31 +80,75 @@ async fn register_client(
 
     let player = Player {
         id: player_id,
-        name: name,
-        token: player_token,
+        name,
+        created_at: SystemTime::now(),
+        rating: Rating::default(),
+        revocations: Default::default(),
+        role: Role::default(),
+        banned: false,
+        bot: None,
         connection: PlayerConnection {
-            sender: None,
+            outbox: None,
             authenticated: false,
         },
     };
 
     let player_to_return = player.clone(); // Another way to do this?
 
-    battlefun.players.insert(player_id, player);
-    battlefun.player_tokens.insert(player_token, player_id);
+    battlefun_instance
+        .register(player)
+        .map_err(reject::custom)?;
 
     Ok(player_to_return)
 }
 
+pub async fn refresh_token_handler(
+    token: PlayerToken,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    let (token, claims) = battlefun_instance
+        .refresh_token(&token)
+        .map_err(reject::custom)?;
+
+    Ok(json(&token_response(token, claims)))
+}
+
+pub async fn revoke_token_handler(
+    token: PlayerToken,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    battlefun_instance
+        .revoke_token(&token, false)
+        .map_err(reject::custom)?;
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn revoke_all_tokens_handler(
+    token: PlayerToken,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    battlefun_instance
+        .revoke_token(&token, true)
+        .map_err(reject::custom)?;
+
+    Ok(StatusCode::OK)
+}
+
+fn token_response(token: PlayerToken, claims: Claims) -> TokenResponse {
+    TokenResponse {
+        token,
+        expires_at: claims.exp,
+    }
+}
+
 pub async fn deregister_handler(
     player_id: PlayerId,
     battlefun_instance: BattleFunInstance,
 ) -> Result<impl Reply> {
-    let mut battlefun = battlefun_instance.write().await;
-
-    let player_token = battlefun.players.get(&player_id).unwrap().token.clone();
-    battlefun.players.remove(&player_id);
-    battlefun.player_tokens.remove(&player_token);
+    battlefun_instance
+        .deregister(&player_id)
+        .map_err(reject::custom)?;
 
     Ok(StatusCode::OK)
 }
@@ -114,18 +158,32 @@ pub async fn ws_handler(
     player_id: PlayerId,
     battlefun_instance: BattleFunInstance,
 ) -> Result<impl Reply> {
-    let player = battlefun_instance
-        .read()
-        .await
-        .players
-        .get(&player_id)
-        .cloned();
-    match player {
-        Some(p) => {
-            Ok(ws.on_upgrade(move |socket| ws::client_connection(socket, p, battlefun_instance)))
-        }
-        None => Err(warp::reject::not_found()),
+    if !battlefun_instance.players.contains_key(&player_id) {
+        return Err(warp::reject::not_found());
+    }
+
+    Ok(ws.on_upgrade(move |socket| ws::client_connection(socket, player_id, battlefun_instance)))
+}
+
+pub async fn spectate_handler(
+    ws: warp::ws::Ws,
+    game_id: GameId,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    if !battlefun_instance.gamemaster.games.contains_key(&game_id) {
+        return Err(reject::custom(Error::NoSuchGame(game_id)));
     }
+
+    Ok(ws.on_upgrade(move |socket| ws::spectator_connection(socket, game_id, battlefun_instance)))
+}
+
+pub async fn metrics_handler(battlefun_instance: BattleFunInstance) -> Result<impl Reply> {
+    Ok(json(&MetricsResponse {
+        players: battlefun_instance.players.len(),
+        games: battlefun_instance.gamemaster.games.len(),
+        queued: battlefun_instance.matchmaker.len(),
+        outbox: battlefun_instance.outbox_metrics.snapshot(),
+    }))
 }
 
 pub async fn health_handler() -> Result<impl Reply> {
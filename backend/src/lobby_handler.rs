@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use warp::{reject, reply::json, Reply};
+
+use crate::{battlefun, game_handler::GenericResponse, BattleFunInstance, Result};
+use battlefun::{
+    lobby::{ChallengeId, LobbyCode},
+    GameId, PlayerId, ShipPlacement,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct LobbyRequest {
+    ships: ShipPlacement,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LobbyResponse {
+    code: LobbyCode,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChallengeRequest {
+    player_id: PlayerId,
+    ships: ShipPlacement,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChallengeResponse {
+    challenge_id: ChallengeId,
+}
+
+#[derive(Serialize, Debug)]
+pub struct GameStartedResponse {
+    game_id: GameId,
+}
+
+pub async fn create_lobby_handler(
+    request: LobbyRequest,
+    player_id: PlayerId,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    let code = battlefun_instance
+        .create_lobby(player_id, request.ships)
+        .map_err(reject::custom)?;
+
+    Ok(json(&LobbyResponse { code }))
+}
+
+pub async fn join_lobby_handler(
+    code: LobbyCode,
+    request: LobbyRequest,
+    player_id: PlayerId,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    let game_id = battlefun_instance
+        .join_lobby(player_id, &code, request.ships)
+        .map_err(reject::custom)?;
+
+    Ok(json(&GameStartedResponse { game_id }))
+}
+
+pub async fn close_lobby_handler(
+    code: LobbyCode,
+    player_id: PlayerId,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    battlefun_instance
+        .lobbies
+        .close(&code, player_id)
+        .map_err(reject::custom)?;
+
+    Ok(json(&GenericResponse::success()))
+}
+
+pub async fn challenge_handler(
+    request: ChallengeRequest,
+    player_id: PlayerId,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    let challenge_id = battlefun_instance
+        .challenge(player_id, request.player_id, request.ships)
+        .map_err(reject::custom)?;
+
+    Ok(json(&ChallengeResponse { challenge_id }))
+}
+
+pub async fn accept_challenge_handler(
+    challenge_id: ChallengeId,
+    request: LobbyRequest,
+    player_id: PlayerId,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    let game_id = battlefun_instance
+        .accept_challenge(player_id, challenge_id, request.ships)
+        .map_err(reject::custom)?;
+
+    Ok(json(&GameStartedResponse { game_id }))
+}
+
+pub async fn decline_challenge_handler(
+    challenge_id: ChallengeId,
+    player_id: PlayerId,
+    battlefun_instance: BattleFunInstance,
+) -> Result<impl Reply> {
+    battlefun_instance
+        .decline_challenge(player_id, challenge_id)
+        .map_err(reject::custom)?;
+
+    Ok(json(&GenericResponse::success()))
+}
@@ -1,26 +1,115 @@
 use std::convert::Infallible;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::{sync::mpsc, time};
 use warp::{http::Method, Filter, Rejection};
 
+mod admin_handler;
+mod auth;
+mod config;
 mod error;
+mod game;
 mod game_handler;
 mod handler;
+mod lobby_handler;
 mod ws;
 
 mod battlefun;
-use battlefun::{BattleFun, GameId, PlayerToken};
+use battlefun::{
+    admin::Role,
+    kafka::{StatefunKafkaClient, StatefunKafkaConsumer},
+    lobby::{ChallengeId, LobbyCode},
+    memory::{self, InMemoryGameEngine},
+    stats::Period,
+    store::{ArchiveStore, FileStore, GameStore, InMemoryStore, PlayerStore},
+    transport::GameEngineTransport,
+    BattleFun, GameId, PlayerId,
+};
+use config::{Config, GameEngine};
 
 type Result<T> = std::result::Result<T, Rejection>;
-type BattleFunInstance = Arc<RwLock<BattleFun>>;
+type BattleFunInstance = Arc<BattleFun>;
 
 #[tokio::main]
 async fn main() {
-    let battlefun = BattleFun::new();
-    let battlefun_instance: BattleFunInstance = Arc::new(RwLock::new(battlefun));
+    let config = Config::from_env();
+
+    let (engine_sender, engine_receiver) = mpsc::unbounded_channel();
+    let transport: Arc<dyn GameEngineTransport> = match config.game_engine {
+        GameEngine::Kafka => Arc::new(StatefunKafkaClient::new(
+            &config.kafka_brokers,
+            config.to_statefun_topic.clone(),
+            engine_sender,
+        )),
+        GameEngine::InMemory => Arc::new(InMemoryGameEngine::new(engine_sender)),
+    };
+
+    let player_store: Arc<PlayerStore> = match &config.player_store {
+        config::StoreKind::InMemory => Arc::new(InMemoryStore::new()),
+        config::StoreKind::File(path) => Arc::new(
+            FileStore::open(path)
+                .unwrap_or_else(|e| panic!("could not open player store {}: {}", path, e)),
+        ),
+    };
+    let game_store: Arc<GameStore> = match &config.game_store {
+        config::StoreKind::InMemory => Arc::new(InMemoryStore::new()),
+        config::StoreKind::File(path) => Arc::new(
+            FileStore::open(path)
+                .unwrap_or_else(|e| panic!("could not open game store {}: {}", path, e)),
+        ),
+    };
+
+    let archive_store: Arc<ArchiveStore> = match &config.archive_store {
+        config::StoreKind::InMemory => Arc::new(InMemoryStore::new()),
+        config::StoreKind::File(path) => Arc::new(
+            FileStore::open(path)
+                .unwrap_or_else(|e| panic!("could not open archive store {}: {}", path, e)),
+        ),
+    };
+
+    let battlefun = BattleFun::new(transport, player_store, game_store, archive_store, &config)
+        .expect("could not load players and games");
+    let battlefun_instance: BattleFunInstance = Arc::new(battlefun);
+
+    // Results reported by the transport itself: all of the in-memory engine's, and the commands
+    // Kafka could not deliver.
+    tokio::task::spawn(memory::forward_updates(
+        engine_receiver,
+        battlefun_instance.clone(),
+    ));
+
+    if let GameEngine::Kafka = config.game_engine {
+        let statefun_kafka_consumer = StatefunKafkaConsumer::new(
+            &config.kafka_brokers,
+            &config.kafka_consumer_group,
+            &config.from_statefun_topic,
+        );
+        let consumer_battlefun_instance = battlefun_instance.clone();
+        tokio::task::spawn(async move {
+            statefun_kafka_consumer
+                .run(consumer_battlefun_instance)
+                .await
+        });
+    }
+
+    let tick_battlefun_instance = battlefun_instance.clone();
+    tokio::task::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            tick_battlefun_instance.sweep_queue();
+            tick_battlefun_instance.check_clocks();
+            tick_battlefun_instance.requests.expire();
+        }
+    });
 
     let health_route = warp::path!("api" / "health").and_then(handler::health_handler);
 
+    let metrics_route = warp::path!("api" / "metrics")
+        .and(warp::get())
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(handler::metrics_handler);
+
     let register_route = warp::path!("api" / "register")
         .and(warp::post())
         .and(warp::body::json())
@@ -29,33 +118,219 @@ async fn main() {
 
     let deregister_route = warp::path!("api" / "deregister")
         .and(warp::post())
-        .and(warp::path::param())
+        .and(auth::with_player(battlefun_instance.clone()))
         .and(with_battlefun_instance(battlefun_instance.clone()))
         .and_then(handler::deregister_handler);
 
+    let refresh_token_route = warp::path!("api" / "token" / "refresh")
+        .and(warp::post())
+        .and(auth::with_token())
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(handler::refresh_token_handler);
+
+    let revoke_token_route = warp::path!("api" / "token")
+        .and(warp::delete())
+        .and(auth::with_token())
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(handler::revoke_token_handler);
+
+    let revoke_all_tokens_route = warp::path!("api" / "token" / "all")
+        .and(warp::delete())
+        .and(auth::with_token())
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(handler::revoke_all_tokens_handler);
+
     let new_game_route = warp::path!("api" / "game")
         .and(warp::post())
         .and(warp::body::json())
-        .and(with_token())
+        .and(auth::with_player(battlefun_instance.clone()))
         .and(with_battlefun_instance(battlefun_instance.clone()))
         .and_then(game_handler::new_game_handler);
 
+    let bot_game_route = warp::path!("api" / "game" / "bot")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(auth::with_player(battlefun_instance.clone()))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(game_handler::bot_game_handler);
+
+    let leave_queue_route = warp::path!("api" / "game" / "queue")
+        .and(warp::delete())
+        .and(auth::with_player(battlefun_instance.clone()))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(game_handler::leave_queue_handler);
+
     let turn_route = warp::path!("api" / "game" / GameId)
         .and(warp::post())
         .and(warp::body::json())
-        .and(with_token())
+        .and(auth::with_player(battlefun_instance.clone()))
         .and(with_battlefun_instance(battlefun_instance.clone()))
         .and_then(game_handler::turn_handler);
 
-    let publish = warp::path!("api" / "publish")
+    let resign_route = warp::path!("api" / "game" / GameId / "resign")
+        .and(warp::post())
+        .and(auth::with_player(battlefun_instance.clone()))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(game_handler::resign_handler);
+
+    let player_games_route = warp::path!("api" / "players" / PlayerId / "games")
+        .and(warp::get())
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(game_handler::player_games_handler);
+
+    let player_stats_route = warp::path!("api" / "players" / PlayerId / "stats")
+        .and(warp::get())
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(game_handler::player_stats_handler);
+
+    let leaderboard_route = warp::path!("api" / "leaderboard" / Period)
+        .and(warp::get())
+        .and(warp::query())
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(game_handler::leaderboard_handler);
+
+    let replay_route = warp::path!("api" / "games" / GameId / "replay")
+        .and(warp::get())
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(game_handler::replay_handler);
+
+    let create_lobby_route = warp::path!("api" / "lobby")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(auth::with_player(battlefun_instance.clone()))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(lobby_handler::create_lobby_handler);
+
+    let join_lobby_route = warp::path!("api" / "lobby" / LobbyCode)
+        .and(warp::post())
         .and(warp::body::json())
+        .and(auth::with_player(battlefun_instance.clone()))
         .and(with_battlefun_instance(battlefun_instance.clone()))
-        .and_then(handler::publish_handler);
+        .and_then(lobby_handler::join_lobby_handler);
 
-    let incoming_kafka_message = warp::path!("api" / "hack")
-        .and(warp::body::bytes())
+    let close_lobby_route = warp::path!("api" / "lobby" / LobbyCode)
+        .and(warp::delete())
+        .and(auth::with_player(battlefun_instance.clone()))
         .and(with_battlefun_instance(battlefun_instance.clone()))
-        .and_then(game_handler::incoming_kafka_message_handler);
+        .and_then(lobby_handler::close_lobby_handler);
+
+    let challenge_route = warp::path!("api" / "challenge")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(auth::with_player(battlefun_instance.clone()))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(lobby_handler::challenge_handler);
+
+    let accept_challenge_route = warp::path!("api" / "challenge" / ChallengeId / "accept")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(auth::with_player(battlefun_instance.clone()))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(lobby_handler::accept_challenge_handler);
+
+    let decline_challenge_route = warp::path!("api" / "challenge" / ChallengeId / "decline")
+        .and(warp::post())
+        .and(auth::with_player(battlefun_instance.clone()))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(lobby_handler::decline_challenge_handler);
+
+    let admin_players_route = warp::path!("api" / "admin" / "players")
+        .and(warp::get())
+        .and(auth::with_staff(
+            battlefun_instance.clone(),
+            Role::Moderator,
+        ))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(admin_handler::list_players_handler);
+
+    let admin_games_route = warp::path!("api" / "admin" / "games")
+        .and(warp::get())
+        .and(auth::with_staff(
+            battlefun_instance.clone(),
+            Role::Moderator,
+        ))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(admin_handler::list_games_handler);
+
+    let admin_abandon_game_route = warp::path!("api" / "admin" / "games" / GameId)
+        .and(warp::delete())
+        .and(auth::with_staff(
+            battlefun_instance.clone(),
+            Role::Moderator,
+        ))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(admin_handler::abandon_game_handler);
+
+    let admin_resign_game_route = warp::path!("api" / "admin" / "games" / GameId / "resign")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(auth::with_staff(
+            battlefun_instance.clone(),
+            Role::Moderator,
+        ))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(admin_handler::resign_game_handler);
+
+    let admin_kick_route = warp::path!("api" / "admin" / "players" / PlayerId / "kick")
+        .and(warp::post())
+        .and(auth::with_staff(
+            battlefun_instance.clone(),
+            Role::Moderator,
+        ))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(admin_handler::kick_handler);
+
+    let admin_ban_route = warp::path!("api" / "admin" / "players" / PlayerId / "ban")
+        .and(warp::put())
+        .and(auth::with_staff(
+            battlefun_instance.clone(),
+            Role::Moderator,
+        ))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(admin_handler::ban_handler);
+
+    let admin_unban_route = warp::path!("api" / "admin" / "players" / PlayerId / "ban")
+        .and(warp::delete())
+        .and(auth::with_staff(
+            battlefun_instance.clone(),
+            Role::Moderator,
+        ))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(admin_handler::unban_handler);
+
+    let admin_role_route = warp::path!("api" / "admin" / "players" / PlayerId / "role")
+        .and(warp::put())
+        .and(warp::body::json())
+        .and(auth::with_staff(battlefun_instance.clone(), Role::Admin))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(admin_handler::set_role_handler);
+
+    let admin_announcement_route = warp::path!("api" / "admin" / "announcements")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and(auth::with_staff(
+            battlefun_instance.clone(),
+            Role::Moderator,
+        ))
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(admin_handler::announcement_handler);
+
+    let admin_routes = admin_players_route
+        .or(admin_games_route)
+        .or(admin_abandon_game_route)
+        .or(admin_resign_game_route)
+        .or(admin_kick_route)
+        .or(admin_ban_route)
+        .or(admin_unban_route)
+        .or(admin_role_route)
+        .or(admin_announcement_route);
+
+    let spectate_route = warp::path!("ws" / "spectate" / ..)
+        .and(warp::ws())
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(with_battlefun_instance(battlefun_instance.clone()))
+        .and_then(handler::spectate_handler);
 
     let ws_route = warp::path("ws")
         .and(warp::ws())
@@ -64,18 +339,35 @@ async fn main() {
         .and_then(handler::ws_handler);
 
     let cors = warp::cors()
-        .allow_methods(&[Method::GET, Method::POST, Method::DELETE])
+        .allow_methods(&[Method::GET, Method::POST, Method::PUT, Method::DELETE])
         .allow_headers(vec!["authorization", "content-type"])
         .allow_any_origin();
 
     let routes = health_route
+        .or(metrics_route)
         .or(register_route)
         .or(deregister_route)
+        .or(refresh_token_route)
+        .or(revoke_token_route)
+        .or(revoke_all_tokens_route)
         .or(new_game_route)
+        .or(bot_game_route)
+        .or(leave_queue_route)
         .or(turn_route)
-        .or(incoming_kafka_message)
+        .or(resign_route)
+        .or(player_games_route)
+        .or(replay_route)
+        .or(player_stats_route)
+        .or(leaderboard_route)
+        .or(create_lobby_route)
+        .or(join_lobby_route)
+        .or(close_lobby_route)
+        .or(challenge_route)
+        .or(accept_challenge_route)
+        .or(decline_challenge_route)
+        .or(spectate_route)
         .or(ws_route)
-        .or(publish)
+        .or(admin_routes)
         .with(cors)
         .recover(error::handle_rejection);
 
@@ -98,12 +390,3 @@ fn with_battlefun_instance(
 ) -> impl Filter<Extract = (BattleFunInstance,), Error = Infallible> + Clone {
     warp::any().map(move || battlefun_instance.clone())
 }
-
-fn with_token() -> impl Filter<Extract = (PlayerToken,), Error = Rejection> + Copy {
-    warp::header::<String>("authorization")
-        .map(|value: String| -> String {
-            let (_, rest) = value.split_at("Bearer ".len());
-            rest.to_string()
-        })
-        .map(|token: String| -> PlayerToken { PlayerToken::parse_str(&token).unwrap() })
-}
@@ -1,143 +1,269 @@
-use crate::{battlefun, BattleFunInstance};
-use futures::{FutureExt, StreamExt};
-use serde::{Deserialize, Serialize};
+use crate::{battlefun, error::Error, BattleFunInstance};
+use futures::{future, pin_mut, StreamExt};
 use serde_json::from_str;
-use tokio::sync::mpsc;
 use warp::ws::{Message, WebSocket};
 
-use battlefun::{Player, PlayerId, PlayerToken};
+use battlefun::protocol::{
+    ClientEnvelope, ClientMessage, ErrorMessage, ErrorReason, ServerMessage, PROTOCOL_VERSION,
+};
+use battlefun::{outbox::Outbox, topics::Topic, GameId, PlayerConnection, PlayerId, PlayerToken};
 
-#[derive(Deserialize, Debug)]
-pub struct TopicsRequest {
-    topics: Vec<String>,
-}
-
-#[derive(Deserialize, Debug)]
-pub struct AuthenticationRequest {
-    r#type: String,
-    token: PlayerToken,
-}
+pub async fn client_connection(
+    ws: WebSocket,
+    player_id: PlayerId,
+    battlefun_instance: BattleFunInstance,
+) {
+    let (client_ws_sender, mut client_ws_rcv) = ws.split();
+    // The outbox only becomes the player's connection once it authenticates as them; until then
+    // it only hears answers to its own messages.
+    let outbox = battlefun_instance.new_outbox();
 
-#[derive(Serialize, Debug)]
-pub struct AuthenticationResponse {
-    r#type: String,
-    success: bool,
-}
+    println!("{} connected", player_id);
 
-impl AuthenticationResponse {
-    pub fn success() -> AuthenticationResponse {
-        AuthenticationResponse {
-            r#type: "authentication_response".to_string(),
-            success: true,
+    let writer = outbox.drain(client_ws_sender);
+    let reader = async {
+        while let Some(result) = client_ws_rcv.next().await {
+            let msg = match result {
+                Ok(msg) => msg,
+                Err(e) => {
+                    eprintln!(
+                        "error receiving ws message for id: {}): {}",
+                        player_id.clone(),
+                        e
+                    );
+                    break;
+                }
+            };
+            client_msg(&player_id, msg, &outbox, &battlefun_instance);
         }
-    }
+    };
+
+    // Whichever finishes first ends the connection: the player hanging up, or the outbox being
+    // closed because they could not keep up.
+    pin_mut!(writer, reader);
+    future::select(writer, reader).await;
+    outbox.close();
+    battlefun_instance.subscriptions.forget(&outbox);
+
+    // Leave the player alone if they have since reconnected: they are still queued, and online.
+    let was_authenticated = battlefun_instance
+        .players
+        .update(&player_id, |player| {
+            let current = player.connection.outbox.as_ref()?;
+            if !current.is_same(&outbox) {
+                return None;
+            }
 
-    pub fn failure() -> AuthenticationResponse {
-        AuthenticationResponse {
-            r#type: "authentication_response".to_string(),
-            success: false,
+            let authenticated = player.connection.authenticated;
+            player.connection = PlayerConnection {
+                outbox: None,
+                authenticated: false,
+            };
+            Some(authenticated)
+        })
+        .flatten();
+
+    if let Some(authenticated) = was_authenticated {
+        battlefun_instance.matchmaker.cancel(&player_id);
+
+        if authenticated {
+            battlefun_instance.announce_presence(player_id, false);
         }
     }
+
+    println!("{} disconnected", player_id);
 }
 
-pub async fn client_connection(
+/// Streams a game to an anonymous viewer, who need not have registered. Anything the viewer sends
+/// other than a ping is ignored.
+pub async fn spectator_connection(
     ws: WebSocket,
-    mut player: Player,
+    game_id: GameId,
     battlefun_instance: BattleFunInstance,
 ) {
-    let player_id = player.id;
     let (client_ws_sender, mut client_ws_rcv) = ws.split();
-    let (client_sender, client_rcv) = mpsc::unbounded_channel();
+    let outbox = battlefun_instance.new_outbox();
 
-    tokio::task::spawn(client_rcv.forward(client_ws_sender).map(|result| {
-        if let Err(e) = result {
-            eprintln!("error sending websocket msg: {}", e);
-        }
-    }));
-
-    // TODO: set connection, not fields
-    player.connection.sender = Some(client_sender);
-    player.connection.authenticated = false;
-
-    battlefun_instance
-        .write()
-        .await
-        .players
-        .insert(player_id, player.clone());
+    if let Err(e) = battlefun_instance.subscribe(Topic::Game(game_id), outbox.clone()) {
+        ServerMessage::Error(ErrorMessage::from(e)).push_to(&outbox);
+        outbox.close();
+        outbox.drain(client_ws_sender).await;
+        return;
+    }
 
-    println!("{} connected", player_id);
+    println!("spectator connected to game {}", game_id);
 
-    while let Some(result) = client_ws_rcv.next().await {
-        let msg = match result {
-            Ok(msg) => msg,
-            Err(e) => {
-                eprintln!(
-                    "error receiving ws message for id: {}): {}",
-                    player_id.clone(),
-                    e
-                );
-                break;
+    let writer = outbox.drain(client_ws_sender);
+    let reader = async {
+        while let Some(Ok(msg)) = client_ws_rcv.next().await {
+            if let Ok(text) = msg.to_str() {
+                if text.trim_end() == "ping" {
+                    ServerMessage::Pong.push_to(&outbox);
+                }
             }
-        };
-        client_msg(&player_id, msg, &battlefun_instance).await;
-    }
-
-    player.connection.sender = None;
-    player.connection.authenticated = false;
+        }
+    };
 
-    battlefun_instance
-        .write()
-        .await
-        .players
-        .insert(player_id, player.clone());
+    pin_mut!(writer, reader);
+    future::select(writer, reader).await;
+    outbox.close();
+    battlefun_instance.subscriptions.forget(&outbox);
 
-    println!("{} disconnected", player_id);
+    println!("spectator disconnected from game {}", game_id);
 }
 
-async fn client_msg(id: &PlayerId, msg: Message, battlefun_instance: &BattleFunInstance) {
-    println!("received message from {}: {:?}", id, msg);
-
+fn client_msg(
+    id: &PlayerId,
+    msg: Message,
+    outbox: &Outbox,
+    battlefun_instance: &BattleFunInstance,
+) {
     let message = match msg.to_str() {
         Ok(v) => v,
         Err(_) => return,
     };
 
     if message == "ping" || message == "ping\n" {
+        ServerMessage::Pong.push_to(outbox);
         return;
     }
 
-    let auth_req: AuthenticationRequest = match from_str(&message) {
+    let envelope: ClientEnvelope = match from_str(message) {
         Ok(r) => r,
         Err(e) => {
-            eprintln!("error while parsing authentication request: {}", e);
+            eprintln!("error while parsing request: {}", e);
+            let error = ErrorMessage::new(ErrorReason::InvalidMessage, e.to_string());
+            ServerMessage::Error(error).push_to(outbox);
             return;
         }
     };
 
-    authenticate(id, auth_req.token, battlefun_instance).await;
-}
-
-async fn authenticate(id: &PlayerId, token: PlayerToken, battlefun_instance: &BattleFunInstance) {
-    let mut battlefun = battlefun_instance.write().await;
-
-    match battlefun.players.get_mut(id) {
-        Some(p) => {
-            if p.token == token {
-                p.connection.authenticated = true;
-
-                if let Some(sender) = &p.connection.sender {
-                    let json = serde_json::to_string(&AuthenticationResponse::success()).unwrap();
-                    let _ = sender.send(Ok(Message::text(json)));
-                }
+    if envelope.version != PROTOCOL_VERSION {
+        let error = ErrorMessage::new(
+            ErrorReason::UnsupportedVersion,
+            format!("unsupported protocol version {}", envelope.version),
+        );
+        ServerMessage::Error(error).push_to(outbox);
+        return;
+    }
 
-                battlefun.player_authenticated(id).await;
+    let result = match envelope.message {
+        ClientMessage::Authentication { token } => {
+            authenticate(id, token, outbox, battlefun_instance);
+            Ok(())
+        }
+        ClientMessage::Ping => {
+            ServerMessage::Pong.push_to(outbox);
+            Ok(())
+        }
+        message => {
+            if battlefun_instance.is_authenticated(id, outbox) {
+                command(*id, message, battlefun_instance)
             } else {
-                if let Some(sender) = &p.connection.sender {
-                    let json = serde_json::to_string(&AuthenticationResponse::failure()).unwrap();
-                    let _ = sender.send(Ok(Message::text(json)));
-                }
+                let error = ErrorMessage::new(ErrorReason::NotAuthenticated, "not authenticated");
+                ServerMessage::Error(error).push_to(outbox);
+                Ok(())
             }
         }
-        None => return,
     };
+
+    if let Err(e) = result {
+        eprintln!("error handling request from {}: {}", id, e);
+        ServerMessage::Error(ErrorMessage::from(e)).push_to(outbox);
+    }
+}
+
+/// Carries out a game command from an authenticated player.
+fn command(
+    id: PlayerId,
+    message: ClientMessage,
+    battlefun_instance: &BattleFunInstance,
+) -> Result<(), Error> {
+    match message {
+        ClientMessage::QueueForGame {
+            ships,
+            bot_fallback,
+        } => battlefun_instance.queue_for_game(id, ships, bot_fallback),
+        ClientMessage::PlayBot { ships, difficulty } => battlefun_instance
+            .play_bot(id, ships, difficulty)
+            .map(|_| ()),
+        ClientMessage::LeaveQueue => battlefun_instance.leave_queue(&id),
+        ClientMessage::CreateLobby { ships } => {
+            let code = battlefun_instance.create_lobby(id, ships)?;
+            battlefun_instance.send(&id, &ServerMessage::LobbyCreated { code });
+            Ok(())
+        }
+        ClientMessage::JoinLobby { code, ships } => {
+            battlefun_instance.join_lobby(id, &code, ships).map(|_| ())
+        }
+        ClientMessage::CloseLobby { code } => battlefun_instance.lobbies.close(&code, id),
+        ClientMessage::Challenge { player_id, ships } => {
+            let challenge_id = battlefun_instance.challenge(id, player_id, ships)?;
+            battlefun_instance.send(
+                &id,
+                &ServerMessage::ChallengeSent {
+                    challenge_id,
+                    player_id,
+                },
+            );
+            Ok(())
+        }
+        ClientMessage::AcceptChallenge {
+            challenge_id,
+            ships,
+        } => battlefun_instance
+            .accept_challenge(id, challenge_id, ships)
+            .map(|_| ()),
+        ClientMessage::DeclineChallenge { challenge_id } => {
+            battlefun_instance.decline_challenge(id, challenge_id)
+        }
+        ClientMessage::TakeShot { game_id, cell } => {
+            battlefun_instance.take_shot(id, game_id, cell).map(|_| ())
+        }
+        ClientMessage::Resign { game_id } => battlefun_instance.gamemaster.resign(game_id, id),
+        ClientMessage::Chat { game_id, message } => battlefun_instance.chat(id, game_id, message),
+        ClientMessage::Subscribe { topics } => battlefun_instance.player_subscribe(id, topics),
+        ClientMessage::Unsubscribe { topics } => {
+            battlefun_instance.player_unsubscribe(id, topics);
+            Ok(())
+        }
+        ClientMessage::Spectate { game_id } => {
+            battlefun_instance.player_subscribe(id, vec![Topic::Game(game_id)])
+        }
+        ClientMessage::StopSpectating { game_id } => {
+            battlefun_instance.player_unsubscribe(id, vec![Topic::Game(game_id)]);
+            Ok(())
+        }
+        ClientMessage::Authentication { .. } | ClientMessage::Ping => Ok(()),
+    }
+}
+
+fn authenticate(
+    id: &PlayerId,
+    token: PlayerToken,
+    outbox: &Outbox,
+    battlefun_instance: &BattleFunInstance,
+) {
+    let valid = match battlefun_instance.authenticate(&token) {
+        Ok(claims) => claims.sub == *id,
+        Err(_) => false,
+    };
+
+    let success = valid
+        && battlefun_instance
+            .players
+            .update(id, |p| {
+                p.connection = PlayerConnection {
+                    outbox: Some(outbox.clone()),
+                    authenticated: true,
+                };
+            })
+            .is_some();
+
+    ServerMessage::AuthenticationResponse { success }.push_to(outbox);
+
+    if success {
+        battlefun_instance.announce_presence(*id, true);
+        battlefun_instance.player_authenticated(id);
+    }
 }
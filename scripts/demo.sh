@@ -22,12 +22,12 @@ player1_token="$(echo "$player1" | jq -Mr .token)"
 curl -s \
   -H "Authorization: Bearer $player1_token" \
   -H 'Content-type: application/json' \
-  -d '{"ships": {"foo": [3, 4, 5]}}' \
+  -d '{"ships": {"carrier": [0, 1, 2, 3, 4], "battleship": [20, 21, 22, 23], "destroyer": [40, 41, 42], "submarine": [60, 61, 62], "patrol_boat": [80, 81]}}' \
   localhost:8000/api/game | jq .
 
 player2_token="$(echo "$player2" | jq -Mr .token)"
 curl -s \
   -H "Authorization: Bearer $player2_token" \
   -H 'Content-type: application/json' \
-  -d '{"ships": {"foo": [3, 4, 5]}}' \
+  -d '{"ships": {"carrier": [0, 1, 2, 3, 4], "battleship": [20, 21, 22, 23], "destroyer": [40, 41, 42], "submarine": [60, 61, 62], "patrol_boat": [80, 81]}}' \
   localhost:8000/api/game | jq .
@@ -27,15 +27,12 @@ import io.battlefun.generated.FromGameFn;
 import io.battlefun.generated.FromGameFn.Builder;
 import io.battlefun.generated.FromGameFn.Failure;
 import io.battlefun.generated.FromGameFn.GameUpdate;
-import io.battlefun.generated.GameStatus;
 import io.battlefun.generated.ToGameFn;
 import io.battlefun.generated.ToGameFn.CreateGame;
 import io.battlefun.generated.ToGameFn.GetGameStatus;
 import io.battlefun.generated.ToGameFn.Resign;
 import io.battlefun.generated.ToGameFn.Turn;
 
-import java.util.Objects;
-
 public final class GameFn implements StatefulFunction {
 
   public static final FunctionType Type = new FunctionType("io.battlefun", "game");
@@ -46,6 +43,7 @@ public final class GameFn implements StatefulFunction {
   public void invoke(Context context, Object message) {
     ToGameFn in = (ToGameFn) message;
     FromGameFn.Builder out = FromGameFn.newBuilder();
+    out.setRequestId(in.getRequestId());
 
     if (in.hasCreateGame()) {
       handleCreateGame(out, in.getCreateGame());
@@ -120,15 +118,12 @@ public final class GameFn implements StatefulFunction {
               .build());
       return;
     }
-    String who = resign.getPlayerId();
-    if (Objects.equals(game.getPlayer1Id(), who)) {
-      // player 1 resigned
-      game = game.toBuilder().setStatus(GameStatus.PLAYER2_WIN).build();
+    Either<GameUpdate, Failure> either = GameLogic.resign(game, resign);
+    if (either.isLeft()) {
+      this.game.set(either.left);
+      resultBuilder.setGameUpdate(either.left);
     } else {
-      // player 2 resigned
-      game = game.toBuilder().setStatus(GameStatus.PLAYER1_WIN).build();
+      resultBuilder.setFailure(either.right);
     }
-    this.game.set(game);
-    resultBuilder.setGameUpdate(game);
   }
 }
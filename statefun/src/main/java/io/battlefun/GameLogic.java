@@ -25,6 +25,7 @@ import io.battlefun.generated.GameStatus;
 import io.battlefun.generated.ShipPlacement;
 import io.battlefun.generated.Shot;
 import io.battlefun.generated.ToGameFn.CreateGame;
+import io.battlefun.generated.ToGameFn.Resign;
 import io.battlefun.generated.ToGameFn.Turn;
 
 import java.util.BitSet;
@@ -89,6 +90,21 @@ final class GameLogic {
     return Either.left(next.build());
   }
 
+  static Either<GameUpdate, Failure> resign(GameUpdate current, Resign resign) {
+    if (isGameOver(current)) {
+      return Either.right(
+          Failure.newBuilder()
+              .setCode(FailureCodes.GAME_ALREADY_FINISHED)
+              .setFailureDescription("The game is already finished")
+              .build());
+    }
+    final int player = Objects.equals(resign.getPlayerId(), current.getPlayer1Id()) ? 0 : 1;
+    final Builder next = current.toBuilder();
+    // the opponent of the resigning player wins
+    setWinner(1 - player, next);
+    return Either.left(next.build());
+  }
+
   private static boolean wasShotPreviouslyTaken(int guessCell, BitSet shotHistory) {
     return shotHistory.get(guessCell);
   }
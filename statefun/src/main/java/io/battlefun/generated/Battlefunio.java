@@ -82,22 +82,23 @@ public final class Battlefunio {
       "p\022\014\n\004type\030\001 \001(\t\022\r\n\005cells\030\002 \003(\003\"2\n\rShipPl" +
       "acement\022!\n\005ships\030\001 \003(\0132\022.io.battlefun.Sh" +
       "ip\"$\n\004Shot\022\017\n\007cell_id\030\001 \001(\003\022\013\n\003hit\030\002 \001(\010" +
-      "\"\275\004\n\010ToGameFn\022\017\n\007game_id\030\001 \001(\t\0228\n\013create" +
-      "_game\030\002 \001(\0132!.io.battlefun.ToGameFn.Crea" +
-      "teGameH\000\022?\n\017get_game_status\030\003 \001(\0132$.io.b" +
-      "attlefun.ToGameFn.GetGameStatusH\000\022+\n\004tur" +
-      "n\030\004 \001(\0132\033.io.battlefun.ToGameFn.TurnH\000\022/" +
-      "\n\006resign\030\005 \001(\0132\035.io.battlefun.ToGameFn.R" +
-      "esignH\000\032\265\001\n\nCreateGame\022\017\n\007game_id\030\001 \001(\t\022" +
-      "\022\n\nplayer1_id\030\002 \001(\t\022\022\n\nplayer2_id\030\003 \001(\t\022" +
-      "6\n\021player1_placement\030\004 \001(\0132\033.io.battlefu" +
-      "n.ShipPlacement\0226\n\021player2_placement\030\005 \001" +
-      "(\0132\033.io.battlefun.ShipPlacement\032 \n\rGetGa" +
-      "meStatus\022\017\n\007game_id\030\001 \001(\t\0328\n\004Turn\022\017\n\007gam" +
-      "e_id\030\001 \001(\t\022\021\n\tplayer_id\030\002 \001(\t\022\014\n\004shot\030\003 " +
-      "\001(\003\032,\n\006Resign\022\017\n\007game_id\030\001 \001(\t\022\021\n\tplayer" +
-      "_id\030\002 \001(\tB\005\n\003msg\"\210\004\n\nFromGameFn\022\017\n\007game_" +
-      "id\030\001 \001(\t\022:\n\013game_update\030\002 \001(\0132#.io.battl" +
+      "\"\321\004\n\010ToGameFn\022\017\n\007game_id\030\001 \001(\t\022\022\n\nreques" +
+      "t_id\030\006 \001(\t\0228\n\013create_game\030\002 \001(\0132!.io.bat" +
+      "tlefun.ToGameFn.CreateGameH\000\022?\n\017get_game" +
+      "_status\030\003 \001(\0132$.io.battlefun.ToGameFn.Ge" +
+      "tGameStatusH\000\022+\n\004turn\030\004 \001(\0132\033.io.battlef" +
+      "un.ToGameFn.TurnH\000\022/\n\006resign\030\005 \001(\0132\035.io." +
+      "battlefun.ToGameFn.ResignH\000\032\265\001\n\nCreateGa" +
+      "me\022\017\n\007game_id\030\001 \001(\t\022\022\n\nplayer1_id\030\002 \001(\t\022" +
+      "\022\n\nplayer2_id\030\003 \001(\t\0226\n\021player1_placement" +
+      "\030\004 \001(\0132\033.io.battlefun.ShipPlacement\0226\n\021p" +
+      "layer2_placement\030\005 \001(\0132\033.io.battlefun.Sh" +
+      "ipPlacement\032 \n\rGetGameStatus\022\017\n\007game_id\030" +
+      "\001 \001(\t\0328\n\004Turn\022\017\n\007game_id\030\001 \001(\t\022\021\n\tplayer" +
+      "_id\030\002 \001(\t\022\014\n\004shot\030\003 \001(\003\032,\n\006Resign\022\017\n\007gam" +
+      "e_id\030\001 \001(\t\022\021\n\tplayer_id\030\002 \001(\tB\005\n\003msg\"\234\004\n" +
+      "\nFromGameFn\022\017\n\007game_id\030\001 \001(\t\022\022\n\nrequest_" +
+      "id\030\004 \001(\t\022:\n\013game_update\030\002 \001(\0132#.io.battl" +
       "efun.FromGameFn.GameUpdateH\000\0223\n\007failure\030" +
       "\003 \001(\0132 .io.battlefun.FromGameFn.FailureH" +
       "\000\0324\n\007Failure\022\014\n\004code\030\001 \001(\003\022\033\n\023failure_de" +
@@ -149,7 +150,7 @@ public final class Battlefunio {
     internal_static_io_battlefun_ToGameFn_fieldAccessorTable = new
       com.google.protobuf.GeneratedMessageV3.FieldAccessorTable(
         internal_static_io_battlefun_ToGameFn_descriptor,
-        new java.lang.String[] { "GameId", "CreateGame", "GetGameStatus", "Turn", "Resign", "Msg", });
+        new java.lang.String[] { "GameId", "RequestId", "CreateGame", "GetGameStatus", "Turn", "Resign", "Msg", });
     internal_static_io_battlefun_ToGameFn_CreateGame_descriptor =
       internal_static_io_battlefun_ToGameFn_descriptor.getNestedTypes().get(0);
     internal_static_io_battlefun_ToGameFn_CreateGame_fieldAccessorTable = new
@@ -179,7 +180,7 @@ public final class Battlefunio {
     internal_static_io_battlefun_FromGameFn_fieldAccessorTable = new
       com.google.protobuf.GeneratedMessageV3.FieldAccessorTable(
         internal_static_io_battlefun_FromGameFn_descriptor,
-        new java.lang.String[] { "GameId", "GameUpdate", "Failure", "Response", });
+        new java.lang.String[] { "GameId", "RequestId", "GameUpdate", "Failure", "Response", });
     internal_static_io_battlefun_FromGameFn_Failure_descriptor =
       internal_static_io_battlefun_FromGameFn_descriptor.getNestedTypes().get(0);
     internal_static_io_battlefun_FromGameFn_Failure_fieldAccessorTable = new
@@ -17,6 +17,7 @@ private static final long serialVersionUID = 0L;
   }
   private FromGameFn() {
     gameId_ = "";
+    requestId_ = "";
   }
 
   @java.lang.Override
@@ -77,6 +78,12 @@ private static final long serialVersionUID = 0L;
             responseCase_ = 3;
             break;
           }
+          case 34: {
+            java.lang.String s = input.readStringRequireUtf8();
+
+            requestId_ = s;
+            break;
+          }
           default: {
             if (!parseUnknownFieldProto3(
                 input, unknownFields, extensionRegistry, tag)) {
@@ -2912,6 +2919,48 @@ private static final long serialVersionUID = 0L;
     }
   }
 
+  public static final int REQUEST_ID_FIELD_NUMBER = 4;
+  private volatile java.lang.Object requestId_;
+  /**
+   * <pre>
+   * the request_id of the ToGameFn this is a response to.
+   * </pre>
+   *
+   * <code>string request_id = 4;</code>
+   */
+  public java.lang.String getRequestId() {
+    java.lang.Object ref = requestId_;
+    if (ref instanceof java.lang.String) {
+      return (java.lang.String) ref;
+    } else {
+      com.google.protobuf.ByteString bs = 
+          (com.google.protobuf.ByteString) ref;
+      java.lang.String s = bs.toStringUtf8();
+      requestId_ = s;
+      return s;
+    }
+  }
+  /**
+   * <pre>
+   * the request_id of the ToGameFn this is a response to.
+   * </pre>
+   *
+   * <code>string request_id = 4;</code>
+   */
+  public com.google.protobuf.ByteString
+      getRequestIdBytes() {
+    java.lang.Object ref = requestId_;
+    if (ref instanceof java.lang.String) {
+      com.google.protobuf.ByteString b = 
+          com.google.protobuf.ByteString.copyFromUtf8(
+              (java.lang.String) ref);
+      requestId_ = b;
+      return b;
+    } else {
+      return (com.google.protobuf.ByteString) ref;
+    }
+  }
+
   public static final int GAME_UPDATE_FIELD_NUMBER = 2;
   /**
    * <code>.io.battlefun.FromGameFn.GameUpdate game_update = 2;</code>
@@ -2987,6 +3036,9 @@ private static final long serialVersionUID = 0L;
     if (responseCase_ == 3) {
       output.writeMessage(3, (io.battlefun.generated.FromGameFn.Failure) response_);
     }
+    if (!getRequestIdBytes().isEmpty()) {
+      com.google.protobuf.GeneratedMessageV3.writeString(output, 4, requestId_);
+    }
     unknownFields.writeTo(output);
   }
 
@@ -3007,6 +3059,9 @@ private static final long serialVersionUID = 0L;
       size += com.google.protobuf.CodedOutputStream
         .computeMessageSize(3, (io.battlefun.generated.FromGameFn.Failure) response_);
     }
+    if (!getRequestIdBytes().isEmpty()) {
+      size += com.google.protobuf.GeneratedMessageV3.computeStringSize(4, requestId_);
+    }
     size += unknownFields.getSerializedSize();
     memoizedSize = size;
     return size;
@@ -3025,6 +3080,8 @@ private static final long serialVersionUID = 0L;
     boolean result = true;
     result = result && getGameId()
         .equals(other.getGameId());
+    result = result && getRequestId()
+        .equals(other.getRequestId());
     result = result && getResponseCase().equals(
         other.getResponseCase());
     if (!result) return false;
@@ -3053,6 +3110,8 @@ private static final long serialVersionUID = 0L;
     hash = (19 * hash) + getDescriptor().hashCode();
     hash = (37 * hash) + GAME_ID_FIELD_NUMBER;
     hash = (53 * hash) + getGameId().hashCode();
+    hash = (37 * hash) + REQUEST_ID_FIELD_NUMBER;
+    hash = (53 * hash) + getRequestId().hashCode();
     switch (responseCase_) {
       case 2:
         hash = (37 * hash) + GAME_UPDATE_FIELD_NUMBER;
@@ -3200,6 +3259,8 @@ private static final long serialVersionUID = 0L;
       super.clear();
       gameId_ = "";
 
+      requestId_ = "";
+
       responseCase_ = 0;
       response_ = null;
       return this;
@@ -3229,6 +3290,7 @@ private static final long serialVersionUID = 0L;
     public io.battlefun.generated.FromGameFn buildPartial() {
       io.battlefun.generated.FromGameFn result = new io.battlefun.generated.FromGameFn(this);
       result.gameId_ = gameId_;
+      result.requestId_ = requestId_;
       if (responseCase_ == 2) {
         if (gameUpdateBuilder_ == null) {
           result.response_ = response_;
@@ -3296,6 +3358,10 @@ private static final long serialVersionUID = 0L;
         gameId_ = other.gameId_;
         onChanged();
       }
+      if (!other.getRequestId().isEmpty()) {
+        requestId_ = other.requestId_;
+        onChanged();
+      }
       switch (other.getResponseCase()) {
         case GAME_UPDATE: {
           mergeGameUpdate(other.getGameUpdate());
@@ -3422,6 +3488,95 @@ private static final long serialVersionUID = 0L;
       return this;
     }
 
+    private java.lang.Object requestId_ = "";
+    /**
+     * <pre>
+     * the request_id of the ToGameFn this is a response to.
+     * </pre>
+     *
+     * <code>string request_id = 4;</code>
+     */
+    public java.lang.String getRequestId() {
+      java.lang.Object ref = requestId_;
+      if (!(ref instanceof java.lang.String)) {
+        com.google.protobuf.ByteString bs =
+            (com.google.protobuf.ByteString) ref;
+        java.lang.String s = bs.toStringUtf8();
+        requestId_ = s;
+        return s;
+      } else {
+        return (java.lang.String) ref;
+      }
+    }
+    /**
+     * <pre>
+     * the request_id of the ToGameFn this is a response to.
+     * </pre>
+     *
+     * <code>string request_id = 4;</code>
+     */
+    public com.google.protobuf.ByteString
+        getRequestIdBytes() {
+      java.lang.Object ref = requestId_;
+      if (ref instanceof String) {
+        com.google.protobuf.ByteString b = 
+            com.google.protobuf.ByteString.copyFromUtf8(
+                (java.lang.String) ref);
+        requestId_ = b;
+        return b;
+      } else {
+        return (com.google.protobuf.ByteString) ref;
+      }
+    }
+    /**
+     * <pre>
+     * the request_id of the ToGameFn this is a response to.
+     * </pre>
+     *
+     * <code>string request_id = 4;</code>
+     */
+    public Builder setRequestId(
+        java.lang.String value) {
+      if (value == null) {
+    throw new NullPointerException();
+  }
+  
+      requestId_ = value;
+      onChanged();
+      return this;
+    }
+    /**
+     * <pre>
+     * the request_id of the ToGameFn this is a response to.
+     * </pre>
+     *
+     * <code>string request_id = 4;</code>
+     */
+    public Builder clearRequestId() {
+      
+      requestId_ = getDefaultInstance().getRequestId();
+      onChanged();
+      return this;
+    }
+    /**
+     * <pre>
+     * the request_id of the ToGameFn this is a response to.
+     * </pre>
+     *
+     * <code>string request_id = 4;</code>
+     */
+    public Builder setRequestIdBytes(
+        com.google.protobuf.ByteString value) {
+      if (value == null) {
+    throw new NullPointerException();
+  }
+  checkByteStringIsUtf8(value);
+      
+      requestId_ = value;
+      onChanged();
+      return this;
+    }
+
     private com.google.protobuf.SingleFieldBuilderV3<
         io.battlefun.generated.FromGameFn.GameUpdate, io.battlefun.generated.FromGameFn.GameUpdate.Builder, io.battlefun.generated.FromGameFn.GameUpdateOrBuilder> gameUpdateBuilder_;
     /**
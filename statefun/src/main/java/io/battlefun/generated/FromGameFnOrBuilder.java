@@ -17,6 +17,24 @@ public interface FromGameFnOrBuilder extends
   com.google.protobuf.ByteString
       getGameIdBytes();
 
+  /**
+   * <pre>
+   * the request_id of the ToGameFn this is a response to.
+   * </pre>
+   *
+   * <code>string request_id = 4;</code>
+   */
+  java.lang.String getRequestId();
+  /**
+   * <pre>
+   * the request_id of the ToGameFn this is a response to.
+   * </pre>
+   *
+   * <code>string request_id = 4;</code>
+   */
+  com.google.protobuf.ByteString
+      getRequestIdBytes();
+
   /**
    * <code>.io.battlefun.FromGameFn.GameUpdate game_update = 2;</code>
    */
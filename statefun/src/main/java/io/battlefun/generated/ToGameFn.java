@@ -17,6 +17,7 @@ private static final long serialVersionUID = 0L;
   }
   private ToGameFn() {
     gameId_ = "";
+    requestId_ = "";
   }
 
   @java.lang.Override
@@ -105,6 +106,12 @@ private static final long serialVersionUID = 0L;
             msgCase_ = 5;
             break;
           }
+          case 50: {
+            java.lang.String s = input.readStringRequireUtf8();
+
+            requestId_ = s;
+            break;
+          }
           default: {
             if (!parseUnknownFieldProto3(
                 input, unknownFields, extensionRegistry, tag)) {
@@ -3413,6 +3420,48 @@ private static final long serialVersionUID = 0L;
     }
   }
 
+  public static final int REQUEST_ID_FIELD_NUMBER = 6;
+  private volatile java.lang.Object requestId_;
+  /**
+   * <pre>
+   * opaque id chosen by the sender, echoed back in the FromGameFn produced for this message.
+   * </pre>
+   *
+   * <code>string request_id = 6;</code>
+   */
+  public java.lang.String getRequestId() {
+    java.lang.Object ref = requestId_;
+    if (ref instanceof java.lang.String) {
+      return (java.lang.String) ref;
+    } else {
+      com.google.protobuf.ByteString bs = 
+          (com.google.protobuf.ByteString) ref;
+      java.lang.String s = bs.toStringUtf8();
+      requestId_ = s;
+      return s;
+    }
+  }
+  /**
+   * <pre>
+   * opaque id chosen by the sender, echoed back in the FromGameFn produced for this message.
+   * </pre>
+   *
+   * <code>string request_id = 6;</code>
+   */
+  public com.google.protobuf.ByteString
+      getRequestIdBytes() {
+    java.lang.Object ref = requestId_;
+    if (ref instanceof java.lang.String) {
+      com.google.protobuf.ByteString b = 
+          com.google.protobuf.ByteString.copyFromUtf8(
+              (java.lang.String) ref);
+      requestId_ = b;
+      return b;
+    } else {
+      return (com.google.protobuf.ByteString) ref;
+    }
+  }
+
   public static final int CREATE_GAME_FIELD_NUMBER = 2;
   /**
    * <code>.io.battlefun.ToGameFn.CreateGame create_game = 2;</code>
@@ -3546,6 +3595,9 @@ private static final long serialVersionUID = 0L;
     if (msgCase_ == 5) {
       output.writeMessage(5, (io.battlefun.generated.ToGameFn.Resign) msg_);
     }
+    if (!getRequestIdBytes().isEmpty()) {
+      com.google.protobuf.GeneratedMessageV3.writeString(output, 6, requestId_);
+    }
     unknownFields.writeTo(output);
   }
 
@@ -3574,6 +3626,9 @@ private static final long serialVersionUID = 0L;
       size += com.google.protobuf.CodedOutputStream
         .computeMessageSize(5, (io.battlefun.generated.ToGameFn.Resign) msg_);
     }
+    if (!getRequestIdBytes().isEmpty()) {
+      size += com.google.protobuf.GeneratedMessageV3.computeStringSize(6, requestId_);
+    }
     size += unknownFields.getSerializedSize();
     memoizedSize = size;
     return size;
@@ -3592,6 +3647,8 @@ private static final long serialVersionUID = 0L;
     boolean result = true;
     result = result && getGameId()
         .equals(other.getGameId());
+    result = result && getRequestId()
+        .equals(other.getRequestId());
     result = result && getMsgCase().equals(
         other.getMsgCase());
     if (!result) return false;
@@ -3628,6 +3685,8 @@ private static final long serialVersionUID = 0L;
     hash = (19 * hash) + getDescriptor().hashCode();
     hash = (37 * hash) + GAME_ID_FIELD_NUMBER;
     hash = (53 * hash) + getGameId().hashCode();
+    hash = (37 * hash) + REQUEST_ID_FIELD_NUMBER;
+    hash = (53 * hash) + getRequestId().hashCode();
     switch (msgCase_) {
       case 2:
         hash = (37 * hash) + CREATE_GAME_FIELD_NUMBER;
@@ -3783,6 +3842,8 @@ private static final long serialVersionUID = 0L;
       super.clear();
       gameId_ = "";
 
+      requestId_ = "";
+
       msgCase_ = 0;
       msg_ = null;
       return this;
@@ -3812,6 +3873,7 @@ private static final long serialVersionUID = 0L;
     public io.battlefun.generated.ToGameFn buildPartial() {
       io.battlefun.generated.ToGameFn result = new io.battlefun.generated.ToGameFn(this);
       result.gameId_ = gameId_;
+      result.requestId_ = requestId_;
       if (msgCase_ == 2) {
         if (createGameBuilder_ == null) {
           result.msg_ = msg_;
@@ -3893,6 +3955,10 @@ private static final long serialVersionUID = 0L;
         gameId_ = other.gameId_;
         onChanged();
       }
+      if (!other.getRequestId().isEmpty()) {
+        requestId_ = other.requestId_;
+        onChanged();
+      }
       switch (other.getMsgCase()) {
         case CREATE_GAME: {
           mergeCreateGame(other.getCreateGame());
@@ -4027,6 +4093,95 @@ private static final long serialVersionUID = 0L;
       return this;
     }
 
+    private java.lang.Object requestId_ = "";
+    /**
+     * <pre>
+     * opaque id chosen by the sender, echoed back in the FromGameFn produced for this message.
+     * </pre>
+     *
+     * <code>string request_id = 6;</code>
+     */
+    public java.lang.String getRequestId() {
+      java.lang.Object ref = requestId_;
+      if (!(ref instanceof java.lang.String)) {
+        com.google.protobuf.ByteString bs =
+            (com.google.protobuf.ByteString) ref;
+        java.lang.String s = bs.toStringUtf8();
+        requestId_ = s;
+        return s;
+      } else {
+        return (java.lang.String) ref;
+      }
+    }
+    /**
+     * <pre>
+     * opaque id chosen by the sender, echoed back in the FromGameFn produced for this message.
+     * </pre>
+     *
+     * <code>string request_id = 6;</code>
+     */
+    public com.google.protobuf.ByteString
+        getRequestIdBytes() {
+      java.lang.Object ref = requestId_;
+      if (ref instanceof String) {
+        com.google.protobuf.ByteString b = 
+            com.google.protobuf.ByteString.copyFromUtf8(
+                (java.lang.String) ref);
+        requestId_ = b;
+        return b;
+      } else {
+        return (com.google.protobuf.ByteString) ref;
+      }
+    }
+    /**
+     * <pre>
+     * opaque id chosen by the sender, echoed back in the FromGameFn produced for this message.
+     * </pre>
+     *
+     * <code>string request_id = 6;</code>
+     */
+    public Builder setRequestId(
+        java.lang.String value) {
+      if (value == null) {
+    throw new NullPointerException();
+  }
+  
+      requestId_ = value;
+      onChanged();
+      return this;
+    }
+    /**
+     * <pre>
+     * opaque id chosen by the sender, echoed back in the FromGameFn produced for this message.
+     * </pre>
+     *
+     * <code>string request_id = 6;</code>
+     */
+    public Builder clearRequestId() {
+      
+      requestId_ = getDefaultInstance().getRequestId();
+      onChanged();
+      return this;
+    }
+    /**
+     * <pre>
+     * opaque id chosen by the sender, echoed back in the FromGameFn produced for this message.
+     * </pre>
+     *
+     * <code>string request_id = 6;</code>
+     */
+    public Builder setRequestIdBytes(
+        com.google.protobuf.ByteString value) {
+      if (value == null) {
+    throw new NullPointerException();
+  }
+  checkByteStringIsUtf8(value);
+      
+      requestId_ = value;
+      onChanged();
+      return this;
+    }
+
     private com.google.protobuf.SingleFieldBuilderV3<
         io.battlefun.generated.ToGameFn.CreateGame, io.battlefun.generated.ToGameFn.CreateGame.Builder, io.battlefun.generated.ToGameFn.CreateGameOrBuilder> createGameBuilder_;
     /**
@@ -17,6 +17,24 @@ public interface ToGameFnOrBuilder extends
   com.google.protobuf.ByteString
       getGameIdBytes();
 
+  /**
+   * <pre>
+   * opaque id chosen by the sender, echoed back in the FromGameFn produced for this message.
+   * </pre>
+   *
+   * <code>string request_id = 6;</code>
+   */
+  java.lang.String getRequestId();
+  /**
+   * <pre>
+   * opaque id chosen by the sender, echoed back in the FromGameFn produced for this message.
+   * </pre>
+   *
+   * <code>string request_id = 6;</code>
+   */
+  com.google.protobuf.ByteString
+      getRequestIdBytes();
+
   /**
    * <code>.io.battlefun.ToGameFn.CreateGame create_game = 2;</code>
    */
@@ -26,6 +26,7 @@ import io.battlefun.generated.ShipPlacement;
 import io.battlefun.generated.ShipPlacement.Builder;
 import io.battlefun.generated.Shot;
 import io.battlefun.generated.ToGameFn.CreateGame;
+import io.battlefun.generated.ToGameFn.Resign;
 import io.battlefun.generated.ToGameFn.Turn;
 import org.hamcrest.CoreMatchers;
 import org.junit.Test;
@@ -132,6 +133,33 @@ public class GameLogicTest {
     assertThat(game.getStatus(), is(GameStatus.PLAYER2_WIN));
   }
 
+  @Test
+  public void opponentWinsOnResign() {
+    GameUpdate game =
+        game(ships("1 2 3", "4 5 6 7", "8 9 10 11"), ships("1 2 3", "4 5 6 7", "8 9 10 11"));
+
+    Either<GameUpdate, Failure> next = GameLogic.resign(game, resign("player-1"));
+
+    assertThat(next.isLeft(), is(true));
+    assertThat(next.left.getStatus(), is(GameStatus.PLAYER2_WIN));
+  }
+
+  @Test
+  public void finishedGameCannotBeResigned() {
+    GameUpdate game =
+        game(ships("1 2 3", "4 5 6 7", "8 9 10 11"), ships("1 2 3", "4 5 6 7", "8 9 10 11"));
+    game = GameLogic.resign(game, resign("player-2")).left;
+
+    Either<GameUpdate, Failure> next = GameLogic.resign(game, resign("player-1"));
+
+    assertThat(next.isLeft(), is(false));
+    assertThat(next.right.getCode(), is(FailureCodes.GAME_ALREADY_FINISHED));
+  }
+
+  private static Resign resign(String playerId) {
+    return Resign.newBuilder().setGameId("game-1").setPlayerId(playerId).build();
+  }
+
   private static Shot shot(int cell) {
     return Shot.newBuilder().setCellId(cell).build();
   }